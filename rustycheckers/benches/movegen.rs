@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rustycheckers::board::Coordinate;
+use rustycheckers::game::GameEngine;
+use rustycheckers::perft::perft;
+
+// a middlegame with kings for both sides
+const MIDGAME: &str = "B:W14,18,20,22,24,25,K27,29,30:B1,3,5,6,9,11,K16,17";
+
+fn targets(c: &mut Criterion) {
+    c.bench_function("move_targets_from", |b| {
+        b.iter(|| black_box(Coordinate(3, 4)).move_targets_from().count())
+    });
+    c.bench_function("jump_targets_from", |b| {
+        b.iter(|| black_box(Coordinate(3, 4)).jump_targets_from().count())
+    });
+}
+
+fn legal_moves(c: &mut Criterion) {
+    let start = GameEngine::new();
+    let midgame = GameEngine::from_position(MIDGAME).unwrap();
+    c.bench_function("legal_moves start", |b| {
+        b.iter(|| black_box(&start).legal_moves())
+    });
+    c.bench_function("legal_moves midgame", |b| {
+        b.iter(|| black_box(&midgame).legal_moves())
+    });
+    c.bench_function("valid_moves_from midgame", |b| {
+        b.iter(|| black_box(&midgame).valid_moves_from(Coordinate(2, 5)))
+    });
+}
+
+fn perft_start(c: &mut Criterion) {
+    let start = GameEngine::new();
+    let mut group = c.benchmark_group("perft");
+    group.sample_size(10);
+    for depth in [4, 6].iter() {
+        group.bench_function(format!("start depth {}", depth), |b| {
+            b.iter(|| perft(black_box(&start), *depth))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, targets, legal_moves, perft_start);
+criterion_main!(benches);
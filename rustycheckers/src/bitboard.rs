@@ -0,0 +1,673 @@
+use super::board::{Coordinate, GamePiece, Move, PieceColor};
+use super::variant::{CaptureRule, Crowning, Variant};
+use std::cmp::Reverse;
+
+// Only the dark squares are ever used, 32 of them on an 8x8 board and 50 on
+// a 10x10 one, so a side fits in a u64. Bit n is PDN square n + 1: rows from
+// Black's back rank (the top row on our board) down to White's, so Black's
+// men move toward the high bits.
+//
+// Rows alternate between starting on the second and the first column, which
+// makes a diagonal step a shift of half the board's width plus or minus 1
+// depending on the row.
+
+// One diagonal direction: which squares can step that way and how far the
+// bits move, for pieces on even rows and on odd rows
+struct Direction {
+    even_mask: u64,
+    even_shift: i32,
+    odd_mask: u64,
+    odd_shift: i32,
+}
+
+// The shapes of the boards the variants are played on
+struct Geometry {
+    // every dark square
+    all: u64,
+    odd_rows: u64,
+    // toward White's side (Black men's forward) and then toward Black's
+    directions: [Direction; 4],
+}
+
+const DOWN: [usize; 2] = [0, 1];
+const UP: [usize; 2] = [2, 3];
+
+impl Geometry {
+    const fn new(size: usize) -> Geometry {
+        let half = size / 2;
+        let squares = half * size;
+        let mut even_rows = 0;
+        let mut first_column = 0;
+        let mut last_column = 0;
+        let mut bit = 0;
+        while bit < squares {
+            if (bit / half).is_multiple_of(2) {
+                even_rows |= 1 << bit;
+            }
+            if bit % half == 0 {
+                first_column |= 1 << bit;
+            }
+            if bit % half == half - 1 {
+                last_column |= 1 << bit;
+            }
+            bit += 1;
+        }
+        let all: u64 = if squares == 64 {
+            !0
+        } else {
+            (1 << squares) - 1
+        };
+        let odd_rows = all & !even_rows;
+        let h = half as i32;
+        Geometry {
+            all,
+            odd_rows,
+            directions: [
+                Direction {
+                    even_mask: even_rows & !last_column,
+                    even_shift: h + 1,
+                    odd_mask: odd_rows,
+                    odd_shift: h,
+                },
+                Direction {
+                    even_mask: even_rows,
+                    even_shift: h,
+                    odd_mask: odd_rows & !first_column,
+                    odd_shift: h - 1,
+                },
+                Direction {
+                    even_mask: even_rows & !last_column,
+                    even_shift: -(h - 1),
+                    odd_mask: odd_rows,
+                    odd_shift: -h,
+                },
+                Direction {
+                    even_mask: even_rows,
+                    even_shift: -h,
+                    odd_mask: odd_rows & !first_column,
+                    odd_shift: -(h + 1),
+                },
+            ],
+        }
+    }
+
+    fn step(&self, direction: usize, bits: u64) -> u64 {
+        let d = &self.directions[direction];
+        (shift(bits & d.even_mask, d.even_shift) | shift(bits & d.odd_mask, d.odd_shift)) & self.all
+    }
+
+    // Where a step to `to` came from; the row parity flips every step
+    fn step_source(&self, direction: usize, to: u32) -> u32 {
+        let d = &self.directions[direction];
+        if (1 << to) & self.odd_rows != 0 {
+            (to as i32 - d.even_shift) as u32
+        } else {
+            (to as i32 - d.odd_shift) as u32
+        }
+    }
+
+    // Two steps always cover one even and one odd row
+    fn jump_source(&self, direction: usize, to: u32) -> u32 {
+        let d = &self.directions[direction];
+        (to as i32 - d.even_shift - d.odd_shift) as u32
+    }
+}
+
+static EIGHT: Geometry = Geometry::new(8);
+static TEN: Geometry = Geometry::new(10);
+
+fn shift(bits: u64, by: i32) -> u64 {
+    if by >= 0 {
+        bits << by
+    } else {
+        bits >> -by
+    }
+}
+
+pub fn square_bit(coord: Coordinate, size: usize) -> Option<u32> {
+    let Coordinate(x, y) = coord;
+    if x >= size || y >= size || (x + y) % 2 == 0 {
+        return None;
+    }
+    let (row, col) = (size - 1 - y, size - 1 - x);
+    Some((row * size / 2 + col / 2) as u32)
+}
+
+pub fn bit_coordinate(bit: u32, size: usize) -> Coordinate {
+    let row = bit as usize / (size / 2);
+    let col = 2 * (bit as usize % (size / 2)) + (1 - row % 2);
+    Coordinate(size - 1 - col, size - 1 - row)
+}
+
+// Indexes of the set bits, lowest first
+fn bits(mut set: u64) -> impl Iterator<Item = u32> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            None
+        } else {
+            let bit = set.trailing_zeros();
+            set &= set - 1;
+            Some(bit)
+        }
+    })
+}
+
+// The directions a color's men move in, and the ones only kings (and men
+// capturing backwards) may take
+fn forward_and_backward(color: PieceColor) -> ([usize; 2], [usize; 2]) {
+    match color {
+        PieceColor::Black => (DOWN, UP),
+        PieceColor::White => (UP, DOWN),
+    }
+}
+
+/// One jump of a capture and the piece it took
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capture {
+    pub mv: Move,
+    pub taken: GamePiece,
+}
+
+/// The pieces on the board, one bit per dark square
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bitboard {
+    pub black: u64,
+    pub white: u64,
+    pub kings: u64,
+    size: usize,
+}
+
+impl Default for Bitboard {
+    fn default() -> Bitboard {
+        Bitboard::new(8)
+    }
+}
+
+impl Bitboard {
+    // An empty board of `size` squares a side, 8 or 10
+    pub fn new(size: usize) -> Bitboard {
+        Bitboard {
+            black: 0,
+            white: 0,
+            kings: 0,
+            size,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn geometry(&self) -> &'static Geometry {
+        match self.size {
+            10 => &TEN,
+            _ => &EIGHT,
+        }
+    }
+
+    pub fn bit(&self, coord: Coordinate) -> Option<u32> {
+        square_bit(coord, self.size)
+    }
+
+    pub fn coordinate(&self, bit: u32) -> Coordinate {
+        bit_coordinate(bit, self.size)
+    }
+
+    pub fn get(&self, coord: Coordinate) -> Option<GamePiece> {
+        self.bit(coord).and_then(|bit| self.piece_at(bit))
+    }
+
+    // Pieces only ever stand on dark squares, anything else is ignored
+    pub fn set(&mut self, coord: Coordinate, piece: Option<GamePiece>) {
+        let mask = match self.bit(coord) {
+            Some(bit) => 1 << bit,
+            None => return,
+        };
+        self.black &= !mask;
+        self.white &= !mask;
+        self.kings &= !mask;
+        if let Some(p) = piece {
+            match p.color {
+                PieceColor::Black => self.black |= mask,
+                PieceColor::White => self.white |= mask,
+            }
+            if p.crowned {
+                self.kings |= mask;
+            }
+        }
+    }
+
+    pub fn pieces(&self, color: PieceColor) -> u64 {
+        match color {
+            PieceColor::Black => self.black,
+            PieceColor::White => self.white,
+        }
+    }
+
+    pub fn empty(&self) -> u64 {
+        self.geometry().all & !(self.black | self.white)
+    }
+
+    pub fn piece_at(&self, bit: u32) -> Option<GamePiece> {
+        let mask = 1 << bit;
+        let color = if self.black & mask != 0 {
+            PieceColor::Black
+        } else if self.white & mask != 0 {
+            PieceColor::White
+        } else {
+            return None;
+        };
+        Some(GamePiece {
+            color,
+            crowned: self.kings & mask != 0,
+        })
+    }
+
+    // The row where a color's men are crowned
+    pub fn crowning_row(&self, color: PieceColor) -> u64 {
+        let half = self.size / 2;
+        let all = self.geometry().all;
+        match color {
+            PieceColor::Black => all & !(all >> half),
+            PieceColor::White => (1 << half) - 1,
+        }
+    }
+
+    // The piece a move from `from` to `to` jumps, if any: the first one
+    // between them on the diagonal
+    pub fn captured_between(&self, from: Coordinate, to: Coordinate) -> Option<Coordinate> {
+        let (Coordinate(fx, fy), Coordinate(tx, ty)) = (from, to);
+        let distance = fx.abs_diff(tx);
+        if distance != fy.abs_diff(ty) {
+            return None;
+        }
+        (1..distance)
+            .map(|i| {
+                let x = if tx > fx { fx + i } else { fx - i };
+                let y = if ty > fy { fy + i } else { fy - i };
+                Coordinate(x, y)
+            })
+            .find(|c| self.get(*c).is_some())
+    }
+
+    // Men only move forward and kings go both ways; men may also capture
+    // backwards when `men_backwards` is set. Yields each direction with the
+    // pieces in `movers` that may go along it.
+    fn directions(
+        &self,
+        color: PieceColor,
+        movers: u64,
+        men_backwards: bool,
+    ) -> impl Iterator<Item = (usize, u64)> {
+        let (forward, backward) = forward_and_backward(color);
+        let back = if men_backwards {
+            movers
+        } else {
+            movers & self.kings
+        };
+        forward
+            .into_iter()
+            .map(move |d| (d, movers))
+            .chain(backward.into_iter().map(move |d| (d, back)))
+    }
+
+    // Pieces that move a square at a time; with flying kings that is men only
+    fn short_movers(&self, variant: &Variant, movers: u64) -> (u64, u64) {
+        if variant.flying_kings {
+            (movers & !self.kings, movers & self.kings)
+        } else {
+            (movers, 0)
+        }
+    }
+
+    // Landing squares of jumps along `direction` from `movers`, skipping the
+    // pieces already taken this turn in `ghosts`
+    fn jump_targets(
+        &self,
+        variant: &Variant,
+        direction: usize,
+        movers: u64,
+        color: PieceColor,
+        ghosts: u64,
+    ) -> u64 {
+        let geometry = self.geometry();
+        let victims = self.pieces(color.opponent()) & !ghosts;
+        let man_victims = if variant.men_capture_kings {
+            victims
+        } else {
+            victims & !self.kings
+        };
+        let over = (geometry.step(direction, movers & !self.kings) & man_victims)
+            | (geometry.step(direction, movers & self.kings) & victims);
+        geometry.step(direction, over) & self.empty() & !ghosts
+    }
+
+    // Captures by a flying king on `bit`: along each diagonal, past any
+    // empty squares, over one piece, onto any empty square beyond it
+    fn flying_captures(&self, bit: u32, color: PieceColor, ghosts: u64) -> Vec<(u32, u32)> {
+        let geometry = self.geometry();
+        let empty = self.empty() & !ghosts;
+        let victims = self.pieces(color.opponent()) & !ghosts;
+        let mut captures = Vec::new();
+        for direction in 0..4 {
+            let mut at = geometry.step(direction, 1 << bit);
+            while at & empty != 0 {
+                at = geometry.step(direction, at);
+            }
+            if at & victims == 0 {
+                continue;
+            }
+            let mut land = geometry.step(direction, at) & empty;
+            while land != 0 {
+                captures.push((land.trailing_zeros(), at.trailing_zeros()));
+                land = geometry.step(direction, land) & empty;
+            }
+        }
+        captures
+    }
+
+    fn flying_steps(&self, bit: u32) -> Vec<u32> {
+        let geometry = self.geometry();
+        let empty = self.empty();
+        let mut steps = Vec::new();
+        for direction in 0..4 {
+            let mut at = geometry.step(direction, 1 << bit) & empty;
+            while at != 0 {
+                steps.push(at.trailing_zeros());
+                at = geometry.step(direction, at) & empty;
+            }
+        }
+        steps
+    }
+
+    // Single jumps for `color`'s pieces on the squares in `from`. Pieces in
+    // `ghosts` were taken earlier in the turn: they can't be jumped again
+    // and still block the way until the turn ends.
+    pub fn jumps(&self, variant: &Variant, color: PieceColor, from: u64, ghosts: u64) -> Vec<Move> {
+        let geometry = self.geometry();
+        let (short, flying) = self.short_movers(variant, from & self.pieces(color));
+        let mut moves = Vec::new();
+        for (direction, movers) in self.directions(color, short, variant.men_capture_backwards) {
+            for to in bits(self.jump_targets(variant, direction, movers, color, ghosts)) {
+                moves.push(Move {
+                    from: self.coordinate(geometry.jump_source(direction, to)),
+                    to: self.coordinate(to),
+                });
+            }
+        }
+        for bit in bits(flying) {
+            for (to, _) in self.flying_captures(bit, color, ghosts) {
+                moves.push(Move {
+                    from: self.coordinate(bit),
+                    to: self.coordinate(to),
+                });
+            }
+        }
+        moves
+    }
+
+    // Non-capturing moves for `color`'s pieces on the squares in `from`
+    pub fn steps(&self, variant: &Variant, color: PieceColor, from: u64) -> Vec<Move> {
+        let geometry = self.geometry();
+        let (short, flying) = self.short_movers(variant, from & self.pieces(color));
+        let mut moves = Vec::new();
+        for (direction, movers) in self.directions(color, short, false) {
+            for to in bits(geometry.step(direction, movers) & self.empty()) {
+                moves.push(Move {
+                    from: self.coordinate(geometry.step_source(direction, to)),
+                    to: self.coordinate(to),
+                });
+            }
+        }
+        for bit in bits(flying) {
+            for to in self.flying_steps(bit) {
+                moves.push(Move {
+                    from: self.coordinate(bit),
+                    to: self.coordinate(to),
+                });
+            }
+        }
+        moves
+    }
+
+    // Every jump and step a side has, without building the moves
+    pub fn count_moves(&self, variant: &Variant, color: PieceColor) -> usize {
+        let geometry = self.geometry();
+        let (short, flying) = self.short_movers(variant, self.pieces(color));
+        let steps: u32 = self
+            .directions(color, short, false)
+            .map(|(direction, movers)| {
+                (geometry.step(direction, movers) & self.empty()).count_ones()
+            })
+            .sum();
+        let jumps: u32 = self
+            .directions(color, short, variant.men_capture_backwards)
+            .map(|(direction, movers)| {
+                self.jump_targets(variant, direction, movers, color, 0)
+                    .count_ones()
+            })
+            .sum();
+        let flights: usize = bits(flying)
+            .map(|bit| self.flying_steps(bit).len() + self.flying_captures(bit, color, 0).len())
+            .sum();
+        (steps + jumps) as usize + flights
+    }
+
+    // The jumps the piece on `bit` can make next, as (landing, taken) bits
+    fn piece_captures(&self, variant: &Variant, bit: u32, ghosts: u64) -> Vec<(u32, u32)> {
+        let piece = match self.piece_at(bit) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        if piece.crowned && variant.flying_kings {
+            return self.flying_captures(bit, piece.color, ghosts);
+        }
+        let geometry = self.geometry();
+        let mut captures = Vec::new();
+        for (direction, movers) in
+            self.directions(piece.color, 1 << bit, variant.men_capture_backwards)
+        {
+            for to in bits(self.jump_targets(variant, direction, movers, piece.color, ghosts)) {
+                let over = geometry.step_source(direction, to);
+                captures.push((to, over));
+            }
+        }
+        captures
+    }
+
+    // Every way the piece on `bit` can carry a capture through to the end,
+    // following the variant's crowning rule part way through
+    fn capture_sequences(&self, variant: &Variant, bit: u32, ghosts: u64) -> Vec<Vec<Capture>> {
+        let mut sequences = Vec::new();
+        self.extend_captures(variant, bit, ghosts, &mut Vec::new(), &mut sequences);
+        sequences
+    }
+
+    fn extend_captures(
+        &self,
+        variant: &Variant,
+        bit: u32,
+        ghosts: u64,
+        path: &mut Vec<Capture>,
+        sequences: &mut Vec<Vec<Capture>>,
+    ) {
+        let captures = self.piece_captures(variant, bit, ghosts);
+        if captures.is_empty() {
+            if !path.is_empty() {
+                sequences.push(path.clone());
+            }
+            return;
+        }
+        let piece = self.piece_at(bit).unwrap();
+        for (to, over) in captures {
+            let mut next = *self;
+            let taken = next.piece_at(over).unwrap();
+            let from = self.coordinate(bit);
+            next.set(self.coordinate(over), None);
+            next.set(from, None);
+            let reached = !piece.crowned && (1 << to) & self.crowning_row(piece.color) != 0;
+            let moved = match variant.crowning {
+                Crowning::EndsTurn | Crowning::ContinuesAsKing if reached => {
+                    GamePiece::crowned(piece)
+                }
+                _ => piece,
+            };
+            next.set(self.coordinate(to), Some(moved));
+            path.push(Capture {
+                mv: Move {
+                    from,
+                    to: self.coordinate(to),
+                },
+                taken,
+            });
+            if reached && variant.crowning == Crowning::EndsTurn {
+                sequences.push(path.clone());
+            } else {
+                next.extend_captures(variant, to, ghosts | 1 << over, path, sequences);
+            }
+            path.pop();
+        }
+    }
+
+    // The first jumps of the captures the variant's capture rule allows
+    // for the pieces in `from`, see `CaptureRule`
+    pub fn best_captures(&self, variant: &Variant, from: u64, ghosts: u64) -> Vec<Move> {
+        let mut best = None;
+        let mut moves = Vec::new();
+        for bit in bits(from & (self.black | self.white)) {
+            let by_king = self.kings & (1 << bit) != 0;
+            for sequence in self.capture_sequences(variant, bit, ghosts) {
+                let rank = capture_rank(variant, by_king, &sequence);
+                if best.is_some_and(|b| rank < b) {
+                    continue;
+                }
+                if best.is_some_and(|b| rank > b) {
+                    moves.clear();
+                }
+                best = Some(rank);
+                if !moves.contains(&sequence[0].mv) {
+                    moves.push(sequence[0].mv);
+                }
+            }
+        }
+        moves
+    }
+}
+
+// How strongly the capture rule prefers a sequence, higher first: the
+// pieces taken, then for Italian rules capturing with a king, the kings
+// taken and how soon the first king is taken
+fn capture_rank(
+    variant: &Variant,
+    by_king: bool,
+    sequence: &[Capture],
+) -> (usize, bool, usize, Reverse<usize>) {
+    if variant.capture_rule != CaptureRule::Italian {
+        return (sequence.len(), false, 0, Reverse(0));
+    }
+    let kings = sequence.iter().filter(|c| c.taken.crowned).count();
+    let first_king = sequence
+        .iter()
+        .position(|c| c.taken.crowned)
+        .unwrap_or(usize::MAX);
+    (sequence.len(), by_king, kings, Reverse(first_king))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::board::Board;
+
+    // Conversions from and to the 8x8 array board, to check one against
+    // the other
+    impl Bitboard {
+        fn from_board(board: &Board) -> Bitboard {
+            let mut bitboard = Bitboard::default();
+            for (x, column) in board.iter().enumerate() {
+                for (y, square) in column.iter().enumerate() {
+                    bitboard.set(Coordinate(x, y), *square);
+                }
+            }
+            bitboard
+        }
+
+        fn to_board(self) -> Board {
+            assert_eq!(self.size, 8, "only 8x8 bitboards convert to a Board");
+            let mut board = [[None; 8]; 8];
+            for bit in bits(self.black | self.white) {
+                let Coordinate(x, y) = self.coordinate(bit);
+                board[x][y] = self.piece_at(bit);
+            }
+            board
+        }
+    }
+
+    #[test]
+    fn squares_map_both_ways() {
+        for size in [8, 10] {
+            for bit in 0..(size * size / 2) as u32 {
+                assert_eq!(square_bit(bit_coordinate(bit, size), size), Some(bit));
+            }
+        }
+        assert_eq!(square_bit(Coordinate(0, 0), 8), None);
+        assert_eq!(square_bit(Coordinate(8, 1), 8), None);
+        assert_eq!(square_bit(Coordinate(9, 0), 10), Some(45));
+
+        let mut board = [[None; 8]; 8];
+        board[3][4] = Some(GamePiece::crowned(GamePiece::new(PieceColor::White)));
+        board[0][5] = Some(GamePiece::new(PieceColor::Black));
+        let bitboard = Bitboard::from_board(&board);
+        assert_eq!(bitboard.to_board(), board);
+        assert_eq!(bitboard.get(Coordinate(3, 4)), board[3][4]);
+    }
+
+    #[test]
+    fn steps_stay_on_the_board() {
+        // a king on every square has 2 to 4 steps and never wraps a row
+        let variant = Variant::american();
+        for bit in 0..32 {
+            let mut bitboard = Bitboard::default();
+            let from = bit_coordinate(bit, 8);
+            bitboard.set(
+                from,
+                Some(GamePiece::crowned(GamePiece::new(PieceColor::Black))),
+            );
+            let steps = bitboard.steps(&variant, PieceColor::Black, !0);
+            let expected = from.move_targets_from().filter(|t| t.on_board()).count();
+            assert_eq!(steps.len(), expected);
+            for mv in steps {
+                let Coordinate(fx, fy) = mv.from;
+                let Coordinate(tx, ty) = mv.to;
+                assert_eq!(mv.from, from);
+                assert_eq!((fx as i32 - tx as i32).abs(), 1);
+                assert_eq!((fy as i32 - ty as i32).abs(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn flying_kings_cover_the_diagonals() {
+        // on an empty 10x10 board a king in the corner sees the whole long
+        // diagonal, and one in the middle sees 17 squares
+        let variant = Variant::international();
+        let king = Some(GamePiece::crowned(GamePiece::new(PieceColor::White)));
+        let mut bitboard = Bitboard::new(10);
+        bitboard.set(Coordinate(0, 9), king);
+        assert_eq!(bitboard.steps(&variant, PieceColor::White, !0).len(), 9);
+
+        let mut bitboard = Bitboard::new(10);
+        bitboard.set(Coordinate(4, 5), king);
+        assert_eq!(bitboard.steps(&variant, PieceColor::White, !0).len(), 17);
+
+        // it may land anywhere past a piece, but not past two in a row
+        bitboard.set(Coordinate(6, 7), Some(GamePiece::new(PieceColor::Black)));
+        bitboard.set(Coordinate(2, 3), Some(GamePiece::new(PieceColor::Black)));
+        bitboard.set(Coordinate(1, 2), Some(GamePiece::new(PieceColor::Black)));
+        let jumps = bitboard.jumps(&variant, PieceColor::White, !0, 0);
+        assert_eq!(jumps.len(), 2);
+        assert_eq!(
+            bitboard.captured_between(jumps[0].from, jumps[0].to),
+            Some(Coordinate(6, 7))
+        );
+    }
+}
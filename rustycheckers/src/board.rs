@@ -4,6 +4,15 @@ pub enum PieceColor {
     Black,
 }
 
+impl PieceColor {
+    pub fn opponent(self) -> PieceColor {
+        match self {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GamePiece {
     pub color: PieceColor,
@@ -26,20 +35,23 @@ impl GamePiece {
     }
 }
 
+// indexed [x][y]
+pub type Board = [[Option<GamePiece>; 8]; 8];
+
 const PIECEFLAG_BLACK: u8 = 1;
 const PIECEFLAG_WHITE: u8 = 2;
 const PIECEFLAG_CROWN: u8 = 4;
 
-impl Into<i32> for GamePiece {
-    fn into(self) -> i32 {
+impl From<GamePiece> for i32 {
+    fn from(piece: GamePiece) -> i32 {
         let mut val: u8 = 0;
-        if self.color == PieceColor::Black {
+        if piece.color == PieceColor::Black {
             val += PIECEFLAG_BLACK;
-        } else if self.color == PieceColor::White {
+        } else if piece.color == PieceColor::White {
             val += PIECEFLAG_WHITE;
         }
 
-        if self.crowned {
+        if piece.crowned {
             val += PIECEFLAG_CROWN;
         }
 
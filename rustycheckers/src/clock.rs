@@ -0,0 +1,159 @@
+use super::board::PieceColor;
+
+// Times are milliseconds, and the host supplies the current time with every
+// call, so the engine never reads a clock of its own.
+
+/// How much time each side gets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeControl {
+    // `base` for the whole game
+    SuddenDeath { base: u64 },
+    // `base`, plus `increment` added after every turn
+    Fischer { base: u64, increment: u64 },
+    // `base`, with the clock held for the first `delay` of every turn
+    Delay { base: u64, delay: u64 },
+}
+
+impl TimeControl {
+    fn base(&self) -> u64 {
+        match *self {
+            TimeControl::SuddenDeath { base }
+            | TimeControl::Fischer { base, .. }
+            | TimeControl::Delay { base, .. } => base,
+        }
+    }
+}
+
+/// Both sides' remaining time. Only the side to move's time runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    control: TimeControl,
+    black: u64,
+    white: u64,
+    running: PieceColor,
+    // when the running side's turn began
+    turn_started: u64,
+}
+
+impl Clock {
+    // Starts `first`'s time running at `now`
+    pub fn new(control: TimeControl, first: PieceColor, now: u64) -> Clock {
+        Clock {
+            control,
+            black: control.base(),
+            white: control.base(),
+            running: first,
+            turn_started: now,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn running(&self) -> PieceColor {
+        self.running
+    }
+
+    // What a turn that has lasted `elapsed` takes off the clock
+    fn charge(&self, elapsed: u64) -> u64 {
+        match self.control {
+            TimeControl::Delay { delay, .. } => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    fn left(&mut self, color: PieceColor) -> &mut u64 {
+        match color {
+            PieceColor::Black => &mut self.black,
+            PieceColor::White => &mut self.white,
+        }
+    }
+
+    // `color`'s time at `now`, counting the turn in progress
+    pub fn remaining(&self, color: PieceColor, now: u64) -> u64 {
+        let left = match color {
+            PieceColor::Black => self.black,
+            PieceColor::White => self.white,
+        };
+        if color == self.running {
+            left.saturating_sub(self.charge(now.saturating_sub(self.turn_started)))
+        } else {
+            left
+        }
+    }
+
+    // The running side has used all its time by `now`
+    pub fn expired(&self, now: u64) -> bool {
+        self.remaining(self.running, now) == 0
+    }
+
+    // Hands the turn to `color` without charging any time, for moves that
+    // aren't timed and moves taken back. The turn in progress carries over.
+    pub fn switch_to(&mut self, color: PieceColor) {
+        self.running = color;
+    }
+
+    // Ends the running side's turn at `now` and starts the other's. Returns
+    // false, changing nothing, when its time had already run out.
+    pub fn press(&mut self, now: u64) -> bool {
+        if self.expired(now) {
+            return false;
+        }
+        let running = self.running;
+        let left = self.remaining(running, now);
+        let increment = match self.control {
+            TimeControl::Fischer { increment, .. } => increment,
+            _ => 0,
+        };
+        *self.left(running) = left + increment;
+        self.running = running.opponent();
+        self.turn_started = now;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn time_controls_charge_turns() {
+        let mut clock = Clock::new(
+            TimeControl::Fischer {
+                base: 60_000,
+                increment: 2_000,
+            },
+            PieceColor::Black,
+            1_000,
+        );
+        assert_eq!(clock.remaining(PieceColor::Black, 6_000), 55_000);
+        assert!(clock.press(6_000));
+        assert_eq!(clock.remaining(PieceColor::Black, 9_000), 57_000);
+        assert_eq!(clock.remaining(PieceColor::White, 9_000), 57_000);
+        assert_eq!(clock.running(), PieceColor::White);
+
+        // nothing comes off until the delay is used up
+        let mut clock = Clock::new(
+            TimeControl::Delay {
+                base: 10_000,
+                delay: 3_000,
+            },
+            PieceColor::White,
+            0,
+        );
+        assert!(clock.press(2_000));
+        assert_eq!(clock.remaining(PieceColor::White, 2_000), 10_000);
+        assert_eq!(clock.remaining(PieceColor::Black, 7_000), 8_000);
+
+        let mut clock = Clock::new(
+            TimeControl::SuddenDeath { base: 5_000 },
+            PieceColor::Black,
+            0,
+        );
+        assert!(!clock.expired(4_999));
+        assert!(clock.expired(5_000));
+        assert!(!clock.press(5_000));
+        assert_eq!(clock.running(), PieceColor::Black);
+    }
+}
@@ -1,84 +1,408 @@
+use super::bitboard::Bitboard;
 use super::board::{Coordinate, GamePiece, Move, PieceColor};
+use super::clock::{Clock, TimeControl};
+use super::pdn::{parse_fen, write_fen, PdnError};
+use super::variant::{CaptureRule, Crowning, Variant};
+use super::zobrist;
+use std::fmt;
 
+#[derive(Clone)]
 pub struct GameEngine {
-    board: [[Option<GamePiece>; 8]; 8],
+    variant: Variant,
+    board: Bitboard,
     current_turn: PieceColor,
     move_count: u32,
+    // piece that must keep jumping before the turn passes
+    jumping_piece: Option<Coordinate>,
+    // pieces taken so far this turn; they stay in the way until it ends
+    ghosts: u64,
+    status: GameStatus,
+    // turns since the last capture or man move
+    quiet_moves: u32,
+    // Zobrist hash of the position, kept up to date as pieces move
+    hash: u64,
+    // the hash at the start of every turn, for spotting repetition
+    hashes: Vec<u64>,
+    // the position the game was set up from
+    start: (Bitboard, PieceColor),
+    history: Vec<HistoryEntry>,
+    // moves taken back with undo, most recent last
+    undone: Vec<Move>,
+    clock: Option<Clock>,
 }
 
+/// Everything needed to take a move back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryEntry {
+    pub mv: Move,
+    // the piece as it was before moving
+    pub piece: GamePiece,
+    pub captured: Option<(Coordinate, GamePiece)>,
+    pub crowned: bool,
+    pub turn_passed: bool,
+    quiet_moves: u32,
+    jumping_piece: Option<Coordinate>,
+    ghosts: u64,
+    hash: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameStatus {
+    InProgress,
+    Won(PieceColor),
+    // the other side ran out of time
+    WonOnTime(PieceColor),
+    Draw(DrawReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawReason {
+    // the same position came up three times with the same side to move
+    Repetition,
+    // 40 moves each with only king moves and no captures
+    MoveLimit,
+}
+
+/// Why a move, undo or redo wasn't made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameError {
+    IllegalMove,
+    // the game is already over
+    GameOver,
+    // the side to move's time ran out, or already had
+    OutOfTime,
+    NothingToUndo,
+    NothingToRedo,
+    OffBoard,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::IllegalMove => write!(f, "illegal move"),
+            GameError::GameOver => write!(f, "the game is over"),
+            GameError::OutOfTime => write!(f, "out of time"),
+            GameError::NothingToUndo => write!(f, "no move to undo"),
+            GameError::NothingToRedo => write!(f, "no move to redo"),
+            GameError::OffBoard => write!(f, "square is off the board"),
+        }
+    }
+}
+
+const REPETITION_LIMIT: usize = 3;
+const QUIET_MOVE_LIMIT: u32 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MoveResult {
     pub mv: Move,
     pub crowned: bool,
+    pub captured: Option<Coordinate>,
+    // the same piece has another jump to make before the turn passes
+    pub turn_in_progress: bool,
+}
+
+impl Default for GameEngine {
+    fn default() -> GameEngine {
+        GameEngine::new()
+    }
 }
 
 impl GameEngine {
     pub fn new() -> GameEngine {
-        let mut engine = GameEngine {
-            board: [[None; 8]; 8],
-            current_turn: PieceColor::Black,
-            move_count: 0,
-        };
+        GameEngine::with_variant(Variant::american())
+    }
+
+    // A new game under another set of rules
+    pub fn with_variant(variant: Variant) -> GameEngine {
+        let board = Bitboard::new(variant.size);
+        let mut engine = GameEngine::set_up(variant, board, variant.first_to_move);
         engine.initialize_pieces();
+        engine.reset_start();
         engine
     }
 
+    // Sets up an American game from a position string, see `pdn::parse_fen`
+    pub fn from_position(position: &str) -> Result<GameEngine, PdnError> {
+        GameEngine::from_position_with(Variant::american(), position)
+    }
+
+    pub fn from_position_with(variant: Variant, position: &str) -> Result<GameEngine, PdnError> {
+        let (board, current_turn) = parse_fen(position, variant.size)?;
+        let mut engine = GameEngine::set_up(variant, board, current_turn);
+        engine.reset_start();
+        engine.status = engine.evaluate_status();
+        Ok(engine)
+    }
+
+    fn set_up(variant: Variant, board: Bitboard, current_turn: PieceColor) -> GameEngine {
+        GameEngine {
+            variant,
+            board,
+            current_turn,
+            move_count: 0,
+            jumping_piece: None,
+            ghosts: 0,
+            status: GameStatus::InProgress,
+            quiet_moves: 0,
+            hash: 0,
+            hashes: Vec::new(),
+            start: (board, current_turn),
+            history: Vec::new(),
+            undone: Vec::new(),
+            clock: None,
+        }
+    }
+
+    // Makes the current board the start of the game
+    fn reset_start(&mut self) {
+        self.start = (self.board, self.current_turn);
+        self.hash = zobrist::hash_position(&self.board, self.current_turn, self.jumping_piece);
+        self.hashes = vec![self.hash];
+    }
+
+    pub fn variant(&self) -> &Variant {
+        &self.variant
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn to_position(&self) -> String {
+        write_fen(&self.board, self.current_turn)
+    }
+
+    // The position the game was set up from
+    pub fn start_position(&self) -> String {
+        let (board, turn) = self.start;
+        write_fen(&board, turn)
+    }
+
+    // Black's men fill the first rows of squares and White's the last
     pub fn initialize_pieces(&mut self) {
-        [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7]
-            .iter()
-            .zip([0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2].iter())
-            .map(|(a, b)| (*a as usize, *b as usize))
-            .for_each(|(x, y)| {
-                self.board[x][y] = Some(GamePiece::new(PieceColor::White));
-            });
+        let squares = self.variant.squares() as u32;
+        let men = (self.variant.rows_of_men * self.variant.size / 2) as u32;
+        for bit in 0..squares {
+            let color = if bit < men {
+                PieceColor::Black
+            } else if bit >= squares - men {
+                PieceColor::White
+            } else {
+                continue;
+            };
+            self.board
+                .set(self.board.coordinate(bit), Some(GamePiece::new(color)));
+        }
+    }
 
-        [0, 2, 4, 6, 1, 3, 5, 7, 0, 2, 4, 6]
-            .iter()
-            .zip([5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7].iter())
-            .map(|(a, b)| (*a as usize, *b as usize))
-            .for_each(|(x, y)| {
-                self.board[x][y] = Some(GamePiece::new(PieceColor::Black));
-            });
+    pub fn move_piece(&mut self, mv: &Move) -> Result<MoveResult, GameError> {
+        let result = self.apply_move(mv)?;
+        self.undone.clear();
+        self.follow_turn();
+        Ok(result)
+    }
+
+    // Plays a move made at `now`, charging the time to the mover's clock
+    // once the turn passes. A move after the mover's time ran out isn't
+    // played and loses the game on time.
+    pub fn move_piece_at(&mut self, mv: &Move, now: u64) -> Result<MoveResult, GameError> {
+        if self.check_clock(now) {
+            return Err(GameError::OutOfTime);
+        }
+        let result = self.apply_move(mv)?;
+        self.undone.clear();
+        self.press_clock(&result, now);
+        Ok(result)
+    }
+
+    fn press_clock(&mut self, result: &MoveResult, now: u64) {
+        if !result.turn_in_progress {
+            if let Some(clock) = self.clock.as_mut() {
+                clock.press(now);
+            }
+        }
+    }
+
+    // Untimed moves and moves taken back hand the clock to the side now to
+    // move without charging anyone
+    fn follow_turn(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.switch_to(self.current_turn);
+        }
+    }
+
+    // Puts the game on a clock, starting the side to move's time at `now`.
+    // Only moves made with `move_piece_at` and `redo_at` are timed.
+    pub fn set_time_control(&mut self, control: TimeControl, now: u64) {
+        self.clock = Some(Clock::new(control, self.current_turn, now));
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    // Ends the game if the side to move's time has run out by `now`.
+    // Returns true when it has.
+    pub fn check_clock(&mut self, now: u64) -> bool {
+        if let GameStatus::WonOnTime(_) = self.status {
+            return true;
+        }
+        match self.clock {
+            Some(clock) if self.status == GameStatus::InProgress && clock.expired(now) => {
+                self.status = GameStatus::WonOnTime(clock.running().opponent());
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn move_piece(&mut self, mv: &Move) -> Result<MoveResult, ()> {
+    // Takes back the last move, restoring the board and turn exactly. Time
+    // used isn't given back, and a loss on time can't be taken back.
+    pub fn undo(&mut self) -> Result<HistoryEntry, GameError> {
+        if let GameStatus::WonOnTime(_) = self.status {
+            return Err(GameError::OutOfTime);
+        }
+        let entry = self.history.pop().ok_or(GameError::NothingToUndo)?;
+        self.board.set(entry.mv.from, Some(entry.piece));
+        self.board.set(entry.mv.to, None);
+        if let Some((coord, captured)) = entry.captured {
+            self.board.set(coord, Some(captured));
+        }
+
+        if entry.turn_passed {
+            self.hashes.pop();
+            self.current_turn = self.current_turn.opponent();
+            self.move_count -= 1;
+        }
+        self.quiet_moves = entry.quiet_moves;
+        self.jumping_piece = entry.jumping_piece;
+        self.ghosts = entry.ghosts;
+        self.hash = entry.hash;
+        // moves are only made while the game is in progress
+        self.status = GameStatus::InProgress;
+        self.undone.push(entry.mv);
+        self.follow_turn();
+        Ok(entry)
+    }
+
+    // Replays the last move taken back with undo
+    pub fn redo(&mut self) -> Result<MoveResult, GameError> {
+        let result = self.replay()?;
+        self.follow_turn();
+        Ok(result)
+    }
+
+    // Replays the last move taken back as if it were made at `now`, like
+    // `move_piece_at`
+    pub fn redo_at(&mut self, now: u64) -> Result<MoveResult, GameError> {
+        if self.check_clock(now) {
+            return Err(GameError::OutOfTime);
+        }
+        let result = self.replay()?;
+        self.press_clock(&result, now);
+        Ok(result)
+    }
+
+    fn replay(&mut self) -> Result<MoveResult, GameError> {
+        let mv = *self.undone.last().ok_or(GameError::NothingToRedo)?;
+        let result = self.apply_move(&mv)?;
+        self.undone.pop();
+        Ok(result)
+    }
+
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    fn apply_move(&mut self, mv: &Move) -> Result<MoveResult, GameError> {
+        if self.status != GameStatus::InProgress {
+            return Err(GameError::GameOver);
+        }
         let legal_moves = self.legal_moves();
 
         if !legal_moves.contains(mv) {
-            return Err(());
+            return Err(GameError::IllegalMove);
         }
 
-        let Coordinate(fx, fy) = mv.from;
-        let Coordinate(tx, ty) = mv.to;
-        let piece = self.board[fx][fy].unwrap();
-        let midpiece_coordinate = self.midpiece_coordinate(fx, fy, tx, ty);
-        if let Some(Coordinate(x, y)) = midpiece_coordinate {
-            self.board[x][y] = None; // remove the jumped piece
+        let piece = self.board.get(mv.from).unwrap();
+        let midpiece_coordinate = self.board.captured_between(mv.from, mv.to);
+        let mut captured = None;
+        let ghosts = self.ghosts;
+        if let Some(coord) = midpiece_coordinate {
+            captured = self.board.get(coord).map(|p| (coord, p));
+            self.board.set(coord, None); // remove the jumped piece
+            self.ghosts |= 1 << self.board.bit(coord).unwrap();
+        }
+        let mut entry = HistoryEntry {
+            mv: *mv,
+            piece,
+            captured,
+            crowned: false,
+            turn_passed: false,
+            quiet_moves: self.quiet_moves,
+            jumping_piece: self.jumping_piece,
+            ghosts,
+            hash: self.hash,
+        };
+        if let Some((coord, p)) = captured {
+            self.hash ^= self.piece_key(coord, p);
         }
 
         // Move piece from source to dest
-        self.board[tx][ty] = Some(piece);
-        self.board[fx][fy] = None;
+        self.board.set(mv.to, Some(piece));
+        self.board.set(mv.from, None);
+        self.hash ^= self.piece_key(mv.from, piece) ^ self.piece_key(mv.to, piece);
 
-        let crowned = if self.should_crown(piece, mv.to) {
-            self.crown_piece(mv.to);
-            true
+        // a man reaching the far row mid-capture may have to wait for the
+        // end of the turn to be crowned, see `Crowning`
+        let reached = self.should_crown(piece, mv.to);
+        let mut crowned = false;
+        if reached && (captured.is_none() || self.variant.crowning != Crowning::OnlyAtEnd) {
+            crowned = self.crown_piece(mv.to);
+        }
+
+        // a capturing piece keeps jumping while it can, unless it was just
+        // crowned and the variant ends the turn there
+        let turn_in_progress = captured.is_some()
+            && !(crowned && self.variant.crowning == Crowning::EndsTurn)
+            && !self.jumps_from(mv.to).is_empty();
+        if let Some(coord) = self.jumping_piece {
+            self.hash ^= zobrist::jumping_key(self.board.bit(coord).unwrap());
+        }
+        if turn_in_progress {
+            self.hash ^= zobrist::jumping_key(self.board.bit(mv.to).unwrap());
+            self.jumping_piece = Some(mv.to);
         } else {
-            false
-        };
-        self.advance_turn();
+            if reached && !crowned {
+                crowned = self.crown_piece(mv.to);
+            }
+            self.jumping_piece = None;
+            self.ghosts = 0;
+            self.advance_turn();
+            self.record_turn(captured.is_some() || !piece.crowned);
+        }
+        entry.crowned = crowned;
+        entry.turn_passed = !turn_in_progress;
+        self.history.push(entry);
 
         Ok(MoveResult {
-            mv: mv.clone(),
-            crowned: crowned,
+            mv: *mv,
+            crowned,
+            captured: midpiece_coordinate,
+            turn_in_progress,
         })
     }
 
-    pub fn get_piece(&self, coord: Coordinate) -> Result<Option<GamePiece>, ()> {
+    fn piece_key(&self, coord: Coordinate, piece: GamePiece) -> u64 {
+        zobrist::piece_key(self.board.bit(coord).unwrap(), piece)
+    }
+
+    pub fn get_piece(&self, coord: Coordinate) -> Result<Option<GamePiece>, GameError> {
         let Coordinate(x, y) = coord;
-        if x <= 7 && y <= 7 {
-            Ok(self.board[x][y])
+        if x < self.variant.size && y < self.variant.size {
+            Ok(self.board.get(coord))
         } else {
-            Err(())
+            Err(GameError::OffBoard)
         }
     }
 
@@ -86,26 +410,66 @@ impl GameEngine {
         self.current_turn
     }
 
+    pub fn status(&self) -> GameStatus {
+        self.status
+    }
+
+    fn record_turn(&mut self, irreversible: bool) {
+        if irreversible {
+            self.quiet_moves = 0;
+        } else {
+            self.quiet_moves += 1;
+        }
+        self.hashes.push(self.hash);
+        self.status = self.evaluate_status();
+    }
+
+    fn evaluate_status(&self) -> GameStatus {
+        // a side with no pieces left has no moves either
+        if self.legal_moves().is_empty() {
+            return GameStatus::Won(self.current_turn.opponent());
+        }
+
+        // captures and man moves can't be reversed, so only positions since
+        // the last of them can come up again
+        let since = self.hashes.len() - 1 - self.quiet_moves as usize;
+        let seen = self.hashes[since..]
+            .iter()
+            .filter(|h| **h == self.hash)
+            .count();
+        if seen >= REPETITION_LIMIT {
+            GameStatus::Draw(DrawReason::Repetition)
+        } else if self.quiet_moves >= QUIET_MOVE_LIMIT {
+            GameStatus::Draw(DrawReason::MoveLimit)
+        } else {
+            GameStatus::InProgress
+        }
+    }
+
     fn advance_turn(&mut self) {
         if self.current_turn == PieceColor::Black {
             self.current_turn = PieceColor::White
         } else {
             self.current_turn = PieceColor::Black
         }
+        self.hash ^= zobrist::turn_key(PieceColor::Black);
         self.move_count += 1;
     }
 
-    // Black pieces in row 0 or White pieces in row 7 are crowned
+    // Black men in row 0 or White men in the last row are crowned
     fn should_crown(&self, piece: GamePiece, coord: Coordinate) -> bool {
         let Coordinate(_x, y) = coord;
 
-        (y == 0 && piece.color == PieceColor::Black) || (y == 7 && piece.color == PieceColor::White)
+        !piece.crowned
+            && ((y == 0 && piece.color == PieceColor::Black)
+                || (y == self.variant.size - 1 && piece.color == PieceColor::White))
     }
 
     fn crown_piece(&mut self, coord: Coordinate) -> bool {
-        let Coordinate(x, y) = coord;
-        if let Some(piece) = self.board[x][y] {
-            self.board[x][y] = Some(GamePiece::crowned(piece));
+        if let Some(piece) = self.board.get(coord) {
+            let king = GamePiece::crowned(piece);
+            self.board.set(coord, Some(king));
+            self.hash ^= self.piece_key(coord, piece) ^ self.piece_key(coord, king);
             true
         } else {
             false
@@ -113,8 +477,7 @@ impl GameEngine {
     }
 
     pub fn is_crowned(&self, coord: Coordinate) -> bool {
-        let Coordinate(x, y) = coord;
-        match self.board[x][y] {
+        match self.board.get(coord) {
             Some(piece) => piece.crowned,
             None => false,
         }
@@ -124,119 +487,116 @@ impl GameEngine {
         self.move_count
     }
 
-    fn legal_moves(&self) -> Vec<Move> {
-        let mut moves: Vec<Move> = Vec::new();
-        for col in 0..8 {
-            for row in 0..8 {
-                if let Some(piece) = self.board[col][row] {
-                    if piece.color == self.current_turn {
-                        let loc = Coordinate(col, row);
-                        let mut vmoves = self.valid_moves_from(loc);
-                        moves.append(&mut vmoves);
-                    }
-                }
-            }
+    // Captures are mandatory: when any piece can jump, only jumps are legal,
+    // and under capture-maximum rules only the first jumps of the best
+    // captures. Nothing is legal once the game is over.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.status != GameStatus::InProgress {
+            return Vec::new();
         }
-
-        moves
-    }
-
-    fn valid_moves_from(&self, loc: Coordinate) -> Vec<Move> {
-        let Coordinate(x, y) = loc;
-        if let Some(p) = self.board[x][y] {
-            let mut jumps = loc
-                .jump_targets_from()
-                .filter(|t| self.valid_jump(&p, &loc, &t))
-                .map(|ref t| Move {
-                    from: loc.clone(),
-                    to: t.clone(),
-                }).collect::<Vec<Move>>();
-            let mut moves = loc
-                .move_targets_from()
-                .filter(|t| self.valid_move(&p, &loc, &t))
-                .map(|ref t| Move {
-                    from: loc.clone(),
-                    to: t.clone(),
-                }).collect::<Vec<Move>>();
-            jumps.append(&mut moves);
-            jumps
-        } else {
-            Vec::new()
+        if let Some(loc) = self.jumping_piece {
+            return self.jumps_from(loc);
         }
-    }
 
-    fn midpiece_coordinate(&self, x: usize, y: usize, tx: usize, ty: usize) -> Option<Coordinate> {
-        if tx == x + 2 && ty == y + 2 {
-            Some(Coordinate(x + 1, y + 1))
-        } else if x >= 2 && y >= 2 && tx == x - 2 && ty == y - 2 {
-            Some(Coordinate(x - 1, y - 1))
-        } else if x >= 2 && tx == x - 2 && ty == y + 2 {
-            Some(Coordinate(x - 1, y + 1))
-        } else if y >= 2 && tx == x + 2 && ty == y - 2 {
-            Some(Coordinate(x + 1, y - 1))
-        } else {
-            None
+        let pieces = self.board.pieces(self.current_turn);
+        let mut moves = self.captures(pieces);
+        let jumps = !moves.is_empty();
+        if !jumps {
+            moves = self.board.steps(&self.variant, self.current_turn, pieces);
         }
+        sort_moves(&mut moves, jumps);
+        moves
     }
 
-    fn midpiece(&self, x: usize, y: usize, tx: usize, ty: usize) -> Option<GamePiece> {
-        match self.midpiece_coordinate(x, y, tx, ty) {
-            Some(Coordinate(x, y)) => self.board[x][y],
-            None => None,
-        }
+    // How many moves a side's pieces have, whoever's turn it is and
+    // ignoring mandatory capture
+    pub fn mobility(&self, color: PieceColor) -> usize {
+        self.board.count_moves(&self.variant, color)
     }
 
-    fn valid_jump(&self, p: &GamePiece, from: &Coordinate, to: &Coordinate) -> bool {
-        if !to.on_board() || !from.on_board() {
-            false
-        } else {
-            let Coordinate(x, y) = *from;
-            let Coordinate(tx, ty) = *to;
+    // The legal moves for the piece at `loc`, empty when it isn't that
+    // piece's turn or another piece has to capture
+    pub fn valid_moves_from(&self, loc: Coordinate) -> Vec<Move> {
+        self.legal_moves()
+            .into_iter()
+            .filter(|m| m.from == loc)
+            .collect()
+    }
 
-            let midpiece = self.midpiece(x, y, tx, ty);
-            match midpiece {
-                Some(mp) if mp.color != p.color => true,
-                _ => false,
+    fn captures(&self, from: u64) -> Vec<Move> {
+        match self.variant.capture_rule {
+            CaptureRule::Free => self
+                .board
+                .jumps(&self.variant, self.current_turn, from, self.ghosts),
+            CaptureRule::Maximum | CaptureRule::Italian => {
+                self.board.best_captures(&self.variant, from, self.ghosts)
             }
         }
     }
 
-    fn valid_move(&self, p: &GamePiece, from: &Coordinate, to: &Coordinate) -> bool {
-        if !to.on_board() || !from.on_board() {
-            false
-        } else {
-            let Coordinate(tx, ty) = *to;
-            if let Some(_piece) = self.board[tx][ty] {
-                false
-            } else {
-                let Coordinate(_fx, fy) = *from;
-                let mut valid = false;
-                if ty > fy && p.color == PieceColor::White {
-                    // white moves down
-                    valid = true;
-                }
-                if ty < fy && p.color == PieceColor::Black {
-                    // black moves up
-                    valid = true;
-                }
-                if ty > fy && p.color == PieceColor::Black && p.crowned {
-                    // crowned black mv down
-                    valid = true;
-                }
-                if ty < fy && p.color == PieceColor::White && p.crowned {
-                    // crowned white mv up
-                    valid = true;
-                }
-                valid
+    fn jumps_from(&self, loc: Coordinate) -> Vec<Move> {
+        match (self.board.get(loc), self.board.bit(loc)) {
+            (Some(p), Some(bit)) if p.color == self.current_turn => {
+                let mut jumps = self.captures(1 << bit);
+                sort_moves(&mut jumps, true);
+                jumps
             }
+            _ => Vec::new(),
         }
     }
 }
 
+// Moves come out grouped by piece, column by column, and for each piece in
+// the order `Coordinate::move_targets_from` and `jump_targets_from` give,
+// nearest first for flying kings
+fn sort_moves(moves: &mut [Move], jumps: bool) {
+    moves.sort_by_key(|m| {
+        let Coordinate(fx, fy) = m.from;
+        let Coordinate(tx, ty) = m.to;
+        let direction = match (tx > fx, ty > fy, jumps) {
+            // jumps
+            (true, false, true) => 0,
+            (true, true, true) => 1,
+            (false, false, true) => 2,
+            (false, true, true) => 3,
+            // steps
+            (false, true, false) => 0,
+            (true, true, false) => 1,
+            (true, false, false) => 2,
+            (false, false, false) => 3,
+        };
+        (fx, fy, direction, fx.abs_diff(tx))
+    });
+}
+
 #[cfg(test)]
 mod test {
+    use super::super::bitboard::Bitboard;
+    use super::super::clock::TimeControl;
     use super::super::board::{Coordinate, GamePiece, Move, PieceColor};
-    use super::GameEngine;
+    use super::super::variant::Variant;
+    use super::super::zobrist;
+    use super::{DrawReason, GameEngine, GameError, GameStatus};
+
+    fn man(color: PieceColor) -> Option<GamePiece> {
+        Some(GamePiece::new(color))
+    }
+
+    fn king(color: PieceColor) -> Option<GamePiece> {
+        Some(GamePiece::crowned(GamePiece::new(color)))
+    }
+
+    // A game under `variant` with only the given pieces, its first player
+    // to move
+    fn set_up(variant: Variant, pieces: &[((usize, usize), Option<GamePiece>)]) -> GameEngine {
+        let mut engine = GameEngine::with_variant(variant);
+        engine.board = Bitboard::new(variant.size);
+        for ((x, y), piece) in pieces.iter() {
+            engine.board.set(Coordinate(*x, *y), *piece);
+        }
+        engine.reset_start();
+        engine
+    }
 
     #[test]
     fn should_crown() {
@@ -245,7 +605,7 @@ mod test {
         let res = engine.should_crown(black, Coordinate(3, 0));
         assert!(res);
         let res_nocrown = engine.should_crown(black, Coordinate(5, 2));
-        assert_eq!(res_nocrown, false);
+        assert!(!res_nocrown);
     }
 
     #[test]
@@ -429,8 +789,9 @@ mod test {
     fn jump_moves_validation() {
         let mut engine = GameEngine::new();
         engine.initialize_pieces();
-        engine.board[1][4] = Some(GamePiece::new(PieceColor::White)); // this should be jumpable from 0,5 to 2,3
+        engine.board.set(Coordinate(1, 4), Some(GamePiece::new(PieceColor::White))); // this should be jumpable from 0,5 to 2,3
         let moves = engine.legal_moves();
+        // captures are mandatory, so only the jumps are legal
         assert_eq!(
             moves,
             [
@@ -441,29 +802,40 @@ mod test {
                 Move {
                     from: Coordinate(2, 5),
                     to: Coordinate(0, 3)
-                },
-                Move {
-                    from: Coordinate(2, 5),
-                    to: Coordinate(3, 4)
-                },
-                Move {
-                    from: Coordinate(4, 5),
-                    to: Coordinate(5, 4)
-                },
-                Move {
-                    from: Coordinate(4, 5),
-                    to: Coordinate(3, 4)
-                },
-                Move {
-                    from: Coordinate(6, 5),
-                    to: Coordinate(7, 4)
-                },
-                Move {
-                    from: Coordinate(6, 5),
-                    to: Coordinate(5, 4)
                 }
             ]
         );
+        assert_eq!(
+            Err(GameError::IllegalMove),
+            engine.move_piece(&Move::new((4, 5), (5, 4)))
+        );
+    }
+
+    #[test]
+    fn multi_jump_keeps_turn() {
+        let mut engine = GameEngine::new();
+        engine.board = Bitboard::default();
+        engine.board.set(Coordinate(1, 6), Some(GamePiece::new(PieceColor::Black)));
+        engine.board.set(Coordinate(2, 5), Some(GamePiece::new(PieceColor::White)));
+        engine.board.set(Coordinate(4, 3), Some(GamePiece::new(PieceColor::White)));
+        engine.board.set(Coordinate(6, 1), Some(GamePiece::new(PieceColor::White)));
+
+        let first = engine.move_piece(&Move::new((1, 6), (3, 4))).unwrap();
+        assert!(first.turn_in_progress);
+        assert_eq!(first.captured, Some(Coordinate(2, 5)));
+        assert_eq!(engine.current_turn(), PieceColor::Black);
+        assert_eq!(engine.legal_moves(), [Move::new((3, 4), (5, 2))]);
+
+        let second = engine.move_piece(&Move::new((3, 4), (5, 2))).unwrap();
+        assert!(second.turn_in_progress);
+
+        // landing on the back row crowns the piece and ends the turn
+        let last = engine.move_piece(&Move::new((5, 2), (7, 0))).unwrap();
+        assert!(last.crowned);
+        assert!(!last.turn_in_progress);
+        assert_eq!(engine.current_turn(), PieceColor::White);
+        assert_eq!(engine.move_count(), 1);
+        assert_eq!(engine.board.get(Coordinate(6, 1)), None);
     }
 
     #[test]
@@ -473,8 +845,8 @@ mod test {
         let res = engine.move_piece(&Move::new((0, 5), (1, 4)));
         assert!(res.is_ok());
 
-        let old = engine.board[0][5];
-        let new = engine.board[1][4];
+        let old = engine.board.get(Coordinate(0, 5));
+        let new = engine.board.get(Coordinate(1, 4));
         assert_eq!(old, None);
         assert_eq!(
             new,
@@ -486,8 +858,319 @@ mod test {
 
         // fail to perform illegal move
         let res = engine.move_piece(&Move::new((1, 4), (2, 4))); // can't move horiz
-        assert!(!res.is_ok());
-        assert_eq!(engine.board[2][4], None);
+        assert_eq!(Err(GameError::IllegalMove), res);
+        assert_eq!(engine.board.get(Coordinate(2, 4)), None);
+    }
+
+    #[test]
+    fn capturing_the_last_piece_wins() {
+        let mut engine = GameEngine::new();
+        engine.board = Bitboard::default();
+        engine.board.set(Coordinate(2, 5), Some(GamePiece::new(PieceColor::Black)));
+        engine.board.set(Coordinate(3, 4), Some(GamePiece::new(PieceColor::White)));
+
+        assert_eq!(engine.status(), GameStatus::InProgress);
+        engine.move_piece(&Move::new((2, 5), (4, 3))).unwrap();
+        assert_eq!(engine.status(), GameStatus::Won(PieceColor::Black));
+        assert_eq!(
+            Err(GameError::GameOver),
+            engine.move_piece(&Move::new((4, 3), (5, 2)))
+        );
+    }
+
+    #[test]
+    fn repeated_king_moves_draw() {
+        let mut engine = GameEngine::new();
+        engine.board = Bitboard::default();
+        engine.board.set(Coordinate(0, 7), Some(GamePiece::crowned(GamePiece::new(PieceColor::Black))));
+        engine.board.set(Coordinate(7, 0), Some(GamePiece::crowned(GamePiece::new(PieceColor::White))));
+        engine.reset_start();
+
+        let shuffle = [
+            Move::new((0, 7), (1, 6)),
+            Move::new((7, 0), (6, 1)),
+            Move::new((1, 6), (0, 7)),
+            Move::new((6, 1), (7, 0)),
+        ];
+        for mv in shuffle.iter().chain(shuffle.iter()) {
+            assert_eq!(engine.status(), GameStatus::InProgress);
+            engine.move_piece(mv).unwrap();
+        }
+        assert_eq!(engine.status(), GameStatus::Draw(DrawReason::Repetition));
+    }
+
+    #[test]
+    fn valid_moves_from_respects_mandatory_capture() {
+        let mut engine = GameEngine::new();
+        engine.board = Bitboard::default();
+        engine.board.set(Coordinate(2, 5), Some(GamePiece::new(PieceColor::Black)));
+        engine.board.set(Coordinate(3, 4), Some(GamePiece::new(PieceColor::White)));
+        engine.board.set(Coordinate(6, 5), Some(GamePiece::new(PieceColor::Black)));
+
+        assert_eq!(
+            engine.valid_moves_from(Coordinate(2, 5)),
+            [Move::new((2, 5), (4, 3))]
+        );
+        assert!(engine.valid_moves_from(Coordinate(6, 5)).is_empty());
+        assert!(engine.valid_moves_from(Coordinate(3, 4)).is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_board() {
+        let mut engine = GameEngine::new();
+        engine.board = Bitboard::default();
+        engine.board.set(Coordinate(3, 4), Some(GamePiece::new(PieceColor::Black)));
+        engine.board.set(Coordinate(4, 3), Some(GamePiece::new(PieceColor::White)));
+        engine.board.set(Coordinate(6, 1), Some(GamePiece::new(PieceColor::White)));
+        engine.board.set(Coordinate(0, 1), Some(GamePiece::new(PieceColor::White)));
+        let start = engine.board;
+
+        // a double jump that ends with a crowning
+        engine.move_piece(&Move::new((3, 4), (5, 2))).unwrap();
+        engine.move_piece(&Move::new((5, 2), (7, 0))).unwrap();
+        assert_eq!(engine.history().len(), 2);
+        assert!(engine.history()[1].crowned);
+        assert_eq!(engine.current_turn(), PieceColor::White);
+        let end = engine.board;
+
+        let last = engine.undo().unwrap();
+        assert_eq!(
+            last.captured,
+            Some((Coordinate(6, 1), GamePiece::new(PieceColor::White)))
+        );
+        assert_eq!(engine.current_turn(), PieceColor::Black);
+        engine.undo().unwrap();
+        assert_eq!(engine.board, start);
+        assert_eq!(Err(GameError::NothingToUndo), engine.undo());
+
+        engine.redo().unwrap();
+        engine.redo().unwrap();
+        assert_eq!(engine.board, end);
+        assert_eq!(Err(GameError::NothingToRedo), engine.redo());
+
+        // a new move drops the moves that were taken back
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.move_piece(&Move::new((3, 4), (5, 2))).unwrap();
+        engine.undo().unwrap();
+        engine.redo().unwrap();
+        assert_eq!(Err(GameError::NothingToRedo), engine.redo());
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let engine = GameEngine::new();
+        let start = engine.to_position();
+        assert_eq!(
+            start,
+            "B:W21,22,23,24,25,26,27,28,29,30,31,32:B1,2,3,4,5,6,7,8,9,10,11,12"
+        );
+        assert_eq!(GameEngine::from_position("B:W21-32:B1-12").unwrap().board, engine.board);
+
+        let puzzle = GameEngine::from_position("W:WK14,30:B9,K23").unwrap();
+        assert_eq!(puzzle.current_turn(), PieceColor::White);
+        assert_eq!(
+            puzzle.get_piece(Coordinate(5, 4)),
+            Ok(Some(GamePiece::crowned(GamePiece::new(PieceColor::White))))
+        );
+        assert_eq!(puzzle.to_position(), "W:WK14,30:B9,K23");
+        assert_eq!(puzzle.start_position(), "W:WK14,30:B9,K23");
+
+        // the side to move has no pieces
+        let over = GameEngine::from_position("B:W1:B").unwrap();
+        assert_eq!(over.status(), GameStatus::Won(PieceColor::White));
+
+        assert!(GameEngine::from_position("X:W1:B2").is_err());
+        assert!(GameEngine::from_position("B:W1,1:B2").is_err());
+        assert!(GameEngine::from_position("B:W33:B2").is_err());
+        assert!(GameEngine::from_position("B:W32-21:B2").is_err());
+    }
+
+    #[test]
+    fn hash_follows_the_position() {
+        let fresh = |engine: &GameEngine| {
+            zobrist::hash_position(&engine.board, engine.current_turn, engine.jumping_piece)
+        };
+        let engine = GameEngine::new();
+        let start = engine.hash();
+        assert_eq!(start, fresh(&engine));
+
+        // a capture with a crowning and a double jump, then both taken back
+        let mut engine = GameEngine::from_position("W:W24,11:B19,10,7").unwrap();
+        let before = engine.hash();
+        engine.move_piece(&Move::new((2, 5), (4, 7))).unwrap();
+        assert!(engine.is_crowned(Coordinate(4, 7)));
+        assert_eq!(engine.hash(), fresh(&engine));
+        engine.undo().unwrap();
+        assert_eq!(engine.hash(), before);
+        engine.move_piece(&Move::new((1, 2), (3, 4))).unwrap();
+        assert_eq!(engine.hash(), fresh(&engine));
+        engine.move_piece(&Move::new((3, 4), (5, 6))).unwrap();
+        assert_eq!(engine.hash(), fresh(&engine));
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.hash(), before);
+
+        // different move orders reaching the same position hash the same
+        let mut a = GameEngine::new();
+        let mut b = GameEngine::new();
+        for mv in [((0, 5), (1, 4)), ((5, 2), (4, 3)), ((4, 5), (5, 4)), ((1, 2), (0, 3))].iter() {
+            a.move_piece(&Move::new(mv.0, mv.1)).unwrap();
+        }
+        for mv in [((4, 5), (5, 4)), ((1, 2), (0, 3)), ((0, 5), (1, 4)), ((5, 2), (4, 3))].iter() {
+            b.move_piece(&Move::new(mv.0, mv.1)).unwrap();
+        }
+        assert_eq!(a.board, b.board);
+        assert_eq!(a.hash(), b.hash());
+        assert_eq!(a.hash(), fresh(&a));
+        assert_ne!(a.hash(), start);
+    }
+
+    #[test]
+    fn russian_men_crown_mid_capture() {
+        let pieces = [
+            ((2, 5), man(PieceColor::White)),
+            ((3, 6), man(PieceColor::Black)),
+            ((5, 6), man(PieceColor::Black)),
+        ];
+        // the new king carries on, flying, over the second man
+        let mut engine = set_up(Variant::russian(), &pieces);
+        let first = engine.move_piece(&Move::new((2, 5), (4, 7))).unwrap();
+        assert!(first.crowned);
+        assert!(first.turn_in_progress);
+        assert_eq!(
+            engine.legal_moves(),
+            [Move::new((4, 7), (6, 5)), Move::new((4, 7), (7, 4))]
+        );
+
+        // under American rules the crowning ends the turn
+        let mut engine = set_up(Variant::american(), &pieces);
+        engine.current_turn = PieceColor::White;
+        engine.reset_start();
+        let first = engine.move_piece(&Move::new((2, 5), (4, 7))).unwrap();
+        assert!(first.crowned);
+        assert!(!first.turn_in_progress);
+    }
+
+    #[test]
+    fn international_captures_take_the_most_pieces() {
+        let mut engine = set_up(
+            Variant::international(),
+            &[
+                ((2, 7), man(PieceColor::White)),
+                ((3, 8), man(PieceColor::Black)),
+                ((5, 8), man(PieceColor::Black)),
+                ((6, 1), man(PieceColor::White)),
+                ((7, 2), man(PieceColor::Black)),
+            ],
+        );
+        assert_eq!(engine.legal_moves(), [Move::new((2, 7), (4, 9))]);
+
+        // passing over the far row doesn't crown, the man carries on
+        // backwards and only ending there would
+        let first = engine.move_piece(&Move::new((2, 7), (4, 9))).unwrap();
+        assert!(!first.crowned);
+        assert!(first.turn_in_progress);
+        let last = engine.move_piece(&Move::new((4, 9), (6, 7))).unwrap();
+        assert!(!last.crowned);
+        assert!(!last.turn_in_progress);
+        assert!(!engine.is_crowned(Coordinate(6, 7)));
+    }
+
+    #[test]
+    fn captured_pieces_block_until_the_turn_ends() {
+        // after taking (5, 6) the king could only reach (1, 2) back over
+        // the square it just emptied, which stays blocked for the turn
+        let mut engine = set_up(
+            Variant::international(),
+            &[
+                ((3, 4), king(PieceColor::White)),
+                ((5, 6), man(PieceColor::Black)),
+                ((7, 8), man(PieceColor::White)),
+                ((1, 2), man(PieceColor::Black)),
+            ],
+        );
+        assert_eq!(
+            engine.legal_moves(),
+            [Move::new((3, 4), (6, 7)), Move::new((3, 4), (0, 1))]
+        );
+        let result = engine.move_piece(&Move::new((3, 4), (6, 7))).unwrap();
+        assert_eq!(result.captured, Some(Coordinate(5, 6)));
+        assert!(!result.turn_in_progress);
+        assert_eq!(engine.current_turn(), PieceColor::Black);
+    }
+
+    #[test]
+    fn italian_capture_priorities() {
+        // men can't take kings
+        let engine = set_up(
+            Variant::italian(),
+            &[
+                ((2, 3), man(PieceColor::White)),
+                ((3, 4), king(PieceColor::Black)),
+            ],
+        );
+        assert_eq!(engine.legal_moves().len(), 1);
+        assert_eq!(engine.legal_moves()[0].to, Coordinate(1, 4));
+
+        // a king takes before a man when both take as many
+        let engine = set_up(
+            Variant::italian(),
+            &[
+                ((5, 2), king(PieceColor::White)),
+                ((6, 3), man(PieceColor::Black)),
+                ((0, 3), man(PieceColor::White)),
+                ((1, 4), man(PieceColor::Black)),
+            ],
+        );
+        assert_eq!(engine.legal_moves(), [Move::new((5, 2), (7, 4))]);
+    }
+
+    #[test]
+    fn running_out_of_time_loses() {
+        let mut engine = GameEngine::new();
+        engine.set_time_control(TimeControl::SuddenDeath { base: 10_000 }, 0);
+
+        engine.move_piece_at(&Move::new((0, 5), (1, 4)), 4_000).unwrap();
+        let clock = engine.clock().unwrap();
+        assert_eq!(clock.remaining(PieceColor::Black, 4_000), 6_000);
+        assert_eq!(clock.remaining(PieceColor::White, 9_000), 5_000);
+
+        assert!(!engine.check_clock(13_999));
+        assert!(engine.check_clock(14_000));
+        assert_eq!(engine.status(), GameStatus::WonOnTime(PieceColor::Black));
+        assert_eq!(
+            Err(GameError::OutOfTime),
+            engine.move_piece_at(&Move::new((1, 2), (2, 3)), 14_000)
+        );
+        assert_eq!(Err(GameError::OutOfTime), engine.undo());
     }
 
+    #[test]
+    fn clock_follows_undo_and_redo() {
+        let mut engine = GameEngine::new();
+        engine.set_time_control(
+            TimeControl::Fischer {
+                base: 10_000,
+                increment: 1_000,
+            },
+            0,
+        );
+        engine.move_piece_at(&Move::new((0, 5), (1, 4)), 4_000).unwrap();
+        assert_eq!(engine.clock().unwrap().running(), PieceColor::White);
+
+        // taking the move back hands the clock back to Black, uncharged
+        engine.undo().unwrap();
+        let clock = *engine.clock().unwrap();
+        assert_eq!(clock.running(), engine.current_turn());
+        assert_eq!(clock.remaining(PieceColor::Black, 4_000), 7_000);
+
+        engine.redo_at(5_000).unwrap();
+        let clock = *engine.clock().unwrap();
+        assert_eq!(clock.running(), PieceColor::White);
+        assert_eq!(clock.remaining(PieceColor::Black, 5_000), 7_000);
+
+        engine.move_piece(&Move::new((1, 2), (2, 3))).unwrap();
+        assert_eq!(engine.clock().unwrap().running(), engine.current_turn());
+    }
 }
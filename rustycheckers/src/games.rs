@@ -0,0 +1,80 @@
+use super::game::GameEngine;
+use std::collections::HashMap;
+
+/// The games in play, by the handle the host refers to each one with.
+/// Handles start at 1 and are never reused, so a stale one can't reach a
+/// newer game.
+pub struct GameTable {
+    games: HashMap<i32, GameEngine>,
+    next_id: i32,
+}
+
+impl GameTable {
+    pub fn new() -> GameTable {
+        GameTable {
+            games: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn insert(&mut self, engine: GameEngine) -> i32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.games.insert(id, engine);
+        id
+    }
+
+    pub fn get(&self, id: i32) -> Option<&GameEngine> {
+        self.games.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut GameEngine> {
+        self.games.get_mut(&id)
+    }
+
+    pub fn remove(&mut self, id: i32) -> Option<GameEngine> {
+        self.games.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+impl Default for GameTable {
+    fn default() -> GameTable {
+        GameTable::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::board::Move;
+    use super::*;
+
+    #[test]
+    fn games_are_kept_apart() {
+        let mut table = GameTable::new();
+        let first = table.insert(GameEngine::new());
+        let second = table.insert(GameEngine::new());
+        assert_ne!(first, second);
+
+        let mv = Move::new((0, 5), (1, 4));
+        table.get_mut(first).unwrap().move_piece(&mv).unwrap();
+        assert_eq!(table.get(first).unwrap().move_count(), 1);
+        assert_eq!(table.get(second).unwrap().move_count(), 0);
+
+        assert!(table.remove(first).is_some());
+        assert!(table.get(first).is_none());
+        assert!(table.remove(first).is_none());
+        assert_eq!(table.len(), 1);
+
+        // handles aren't handed out twice
+        let third = table.insert(GameEngine::new());
+        assert!(third != first && third != second);
+    }
+}
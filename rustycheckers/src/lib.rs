@@ -1,61 +1,394 @@
 #[macro_use]
 extern crate lazy_static;
 
-mod board;
-mod game;
+pub mod bitboard;
+pub mod board;
+pub mod clock;
+pub mod game;
+pub mod games;
+pub mod pdn;
+pub mod perft;
+pub mod search;
+pub mod transposition;
+pub mod variant;
+pub mod zobrist;
 
-use board::{Coordinate, GamePiece, Move};
-use game::GameEngine;
+use board::{Coordinate, GamePiece, Move, PieceColor};
+use clock::TimeControl;
+use game::{DrawReason, GameEngine, GameError, GameStatus};
+use games::GameTable;
 use mut_static::MutStatic;
 use crate::game::MoveResult;
+use variant::Variant;
 
 lazy_static! {
-    pub static ref GAME_ENGINE: MutStatic<GameEngine> =  MutStatic::from(GameEngine::new());
+    pub static ref GAMES: MutStatic<GameTable> =  MutStatic::from(GameTable::new());
 }
 
-// expose two methods: get_piece, move_piece and get_current_turn as webassembly functions.
+// Every game export takes the handle new_game returned as its first
+// argument, and the notifications say which game they are about.
 
 extern "C" {
-    fn notify_piecemoved(fromX: i32, fromY: i32, toX: i32, toY: i32);
-    fn notify_piececrowned(x: i32, y: i32);
+    fn notify_piecemoved(game: i32, fromX: i32, fromY: i32, toX: i32, toY: i32);
+    fn notify_piececrowned(game: i32, x: i32, y: i32);
+    fn notify_gameover(game: i32, status: i32);
 }
 
+// game status codes shared by get_game_status and notify_gameover
+const STATUS_IN_PROGRESS: i32 = 0;
+const STATUS_BLACK_WON: i32 = 1;
+const STATUS_WHITE_WON: i32 = 2;
+const STATUS_DRAW_REPETITION: i32 = 3;
+const STATUS_DRAW_MOVE_LIMIT: i32 = 4;
+const STATUS_BLACK_WON_ON_TIME: i32 = 5;
+const STATUS_WHITE_WON_ON_TIME: i32 = 6;
+
+fn status_code(status: GameStatus) -> i32 {
+    match status {
+        GameStatus::InProgress => STATUS_IN_PROGRESS,
+        GameStatus::Won(PieceColor::Black) => STATUS_BLACK_WON,
+        GameStatus::Won(PieceColor::White) => STATUS_WHITE_WON,
+        GameStatus::WonOnTime(PieceColor::Black) => STATUS_BLACK_WON_ON_TIME,
+        GameStatus::WonOnTime(PieceColor::White) => STATUS_WHITE_WON_ON_TIME,
+        GameStatus::Draw(DrawReason::Repetition) => STATUS_DRAW_REPETITION,
+        GameStatus::Draw(DrawReason::MoveLimit) => STATUS_DRAW_MOVE_LIMIT,
+    }
+}
+
+// Runs `f` on game `id`, or returns `missing` when there is no such game
+fn with_game<T>(id: i32, missing: T, f: impl FnOnce(&GameEngine) -> T) -> T {
+    let games = GAMES.read().unwrap();
+    games.get(id).map_or(missing, f)
+}
+
+fn with_game_mut<T>(id: i32, missing: T, f: impl FnOnce(&mut GameEngine) -> T) -> T {
+    let mut games = GAMES.write().unwrap();
+    games.get_mut(id).map_or(missing, f)
+}
+
+// Starts an American game and returns its handle
+#[no_mangle]
+pub extern "C" fn new_game() -> i32 {
+    GAMES.write().unwrap().insert(GameEngine::new())
+}
+
+// Starts a game under the rules with PDN GameType `game_type` (20
+// international, 21 American, 22 Italian, 25 Russian) and returns its
+// handle, or -1 for rules the engine doesn't know
 #[no_mangle]
-pub extern "C" fn get_piece(x: i32, y:i32) -> i32 {
-    let engine = GAME_ENGINE.read().unwrap();
+pub extern "C" fn new_variant_game(game_type: i32) -> i32 {
+    match u8::try_from(game_type).ok().and_then(Variant::from_game_type) {
+        Some(variant) => GAMES.write().unwrap().insert(GameEngine::with_variant(variant)),
+        None => -1,
+    }
+}
 
-    let piece = engine.get_piece(Coordinate(x as usize, y as usize));
-    match piece {
-        Ok(Some(p)) => p.into(),
-        Ok(None) => -1,
-        Err(_) => -1,
+// Ends a game and frees its memory. Returns 1, or 0 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn free_game(id: i32) -> i32 {
+    match GAMES.write().unwrap().remove(id) {
+        Some(_) => 1,
+        None => 0,
     }
 }
 
 #[no_mangle]
-pub extern "C" fn get_current_turn() -> i32 {
-    let engine = GAME_ENGINE.read().unwrap();
-    GamePiece::new(engine.current_turn()).into()
+pub extern "C" fn get_piece(id: i32, x: i32, y:i32) -> i32 {
+    with_game(id, -1, |engine| {
+        let piece = engine.get_piece(Coordinate(x as usize, y as usize));
+        match piece {
+            Ok(Some(p)) => p.into(),
+            Ok(None) => -1,
+            Err(_) => -1,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_current_turn(id: i32) -> i32 {
+    with_game(id, -1, |engine| GamePiece::new(engine.current_turn()).into())
+}
+
+#[no_mangle]
+pub extern "C" fn get_game_status(id: i32) -> i32 {
+    with_game(id, -1, |engine| status_code(engine.status()))
+}
+
+// The board's width, 8 or 10, so the host knows how many squares to draw
+#[no_mangle]
+pub extern "C" fn get_board_size(id: i32) -> i32 {
+    with_game(id, -1, |engine| engine.variant().size as i32)
+}
+
+// Moves cross the wasm boundary packed into one i32, four bits per
+// coordinate: fromX << 12 | fromY << 8 | toX << 4 | toY
+fn pack_move(mv: &Move) -> i32 {
+    let Coordinate(fx, fy) = mv.from;
+    let Coordinate(tx, ty) = mv.to;
+    ((fx << 12) | (fy << 8) | (tx << 4) | ty) as i32
 }
 
+fn packed_move_at(moves: &[Move], index: i32) -> i32 {
+    if index < 0 {
+        return -1;
+    }
+    moves.get(index as usize).map_or(-1, pack_move)
+}
+
+#[no_mangle]
+pub extern "C" fn get_legal_move_count(id: i32) -> i32 {
+    with_game(id, 0, |engine| engine.legal_moves().len() as i32)
+}
+
+// Returns the packed move at `index`, or -1 when out of range
+#[no_mangle]
+pub extern "C" fn get_legal_move(id: i32, index: i32) -> i32 {
+    with_game(id, -1, |engine| packed_move_at(&engine.legal_moves(), index))
+}
+
+#[no_mangle]
+pub extern "C" fn get_valid_move_count_from(id: i32, x: i32, y: i32) -> i32 {
+    with_game(id, 0, |engine| {
+        engine
+            .valid_moves_from(Coordinate(x as usize, y as usize))
+            .len() as i32
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_valid_move_from(id: i32, x: i32, y: i32, index: i32) -> i32 {
+    with_game(id, -1, |engine| {
+        packed_move_at(
+            &engine.valid_moves_from(Coordinate(x as usize, y as usize)),
+            index,
+        )
+    })
+}
+
+
+// Returns 0 for an illegal move or unknown game, 1 when the turn passes to
+// the other side and 2 when the same piece has to keep jumping
+#[no_mangle]
+pub extern "C" fn move_piece(id: i32, fx: i32, fy: i32, tx: i32, ty: i32) -> i32 {
+    with_game_mut(id, 0, |engine| {
+        let mv: Move = Move::new((fx as usize, fy as usize), (tx as usize, ty as usize));
+        let res: Result<MoveResult, GameError> = engine.move_piece(&mv);
+        match res {
+            Ok(mr) => notify_move(id, engine, &mr),
+            Err(_) => 0,
+        }
+    })
+}
+
+// time control kinds for set_time_control
+const CONTROL_SUDDEN_DEATH: i32 = 0;
+const CONTROL_FISCHER: i32 = 1;
+const CONTROL_DELAY: i32 = 2;
+
+// Host times are milliseconds as JavaScript numbers, e.g. performance.now()
+fn millis(time: f64) -> u64 {
+    time.max(0.0) as u64
+}
+
+// Puts a game on a clock: `kind` is 0 for sudden death, 1 for a Fischer
+// increment or 2 for a delay, with `base` ms for each side and `extra` ms
+// of increment or delay. The side to move's time starts running at `now`.
+// Returns 1, or 0 for an unknown game or kind.
+#[no_mangle]
+pub extern "C" fn set_time_control(id: i32, kind: i32, base: f64, extra: f64, now: f64) -> i32 {
+    let (base, extra) = (millis(base), millis(extra));
+    let control = match kind {
+        CONTROL_SUDDEN_DEATH => TimeControl::SuddenDeath { base },
+        CONTROL_FISCHER => TimeControl::Fischer { base, increment: extra },
+        CONTROL_DELAY => TimeControl::Delay { base, delay: extra },
+        _ => return 0,
+    };
+    with_game_mut(id, 0, |engine| {
+        engine.set_time_control(control, millis(now));
+        1
+    })
+}
 
+// Like move_piece for a move made at `now`, which is charged to the
+// mover's clock. Returns 3 when the mover's time had already run out: the
+// move isn't played and the game is lost on time.
 #[no_mangle]
-pub extern "C" fn move_piece(fx: i32, fy: i32, tx: i32, ty: i32) -> i32 {
-    let mut engine = GAME_ENGINE.write().unwrap();
-    let mv: Move = Move::new((fx as usize, fy as usize), (tx as usize, ty as usize));
-    let res: Result<MoveResult, ()> = engine.move_piece(&mv);
-    match res {
-        Ok(mr ) => {
-            unsafe {
-                notify_piecemoved( fx,  fy, tx, ty);
+pub extern "C" fn move_piece_at(id: i32, fx: i32, fy: i32, tx: i32, ty: i32, now: f64) -> i32 {
+    with_game_mut(id, 0, |engine| {
+        let mv: Move = Move::new((fx as usize, fy as usize), (tx as usize, ty as usize));
+        let running = engine.status() == GameStatus::InProgress;
+        match engine.move_piece_at(&mv, millis(now)) {
+            Ok(mr) => notify_move(id, engine, &mr),
+            Err(GameError::OutOfTime) if running => {
+                notify_time_loss(id, engine);
+                3
             }
-            if mr.crowned {
-                unsafe {
-                    notify_piececrowned(tx, ty);
-                }
+            Err(_) => 0,
+        }
+    })
+}
+
+// Milliseconds left at `now` for `color` (1 Black, 2 White, as from
+// get_current_turn), or -1 when the game has no clock
+#[no_mangle]
+pub extern "C" fn get_remaining_time(id: i32, color: i32, now: f64) -> f64 {
+    let color = match color {
+        1 => PieceColor::Black,
+        2 => PieceColor::White,
+        _ => return -1.0,
+    };
+    with_game(id, -1.0, |engine| {
+        engine
+            .clock()
+            .map_or(-1.0, |c| c.remaining(color, millis(now)) as f64)
+    })
+}
+
+// For the host to call while it waits for a move: ends the game when the
+// side to move's time has run out by `now`, and returns the game status
+#[no_mangle]
+pub extern "C" fn check_time(id: i32, now: f64) -> i32 {
+    with_game_mut(id, -1, |engine| {
+        let running = engine.status() == GameStatus::InProgress;
+        if engine.check_clock(millis(now)) && running {
+            notify_time_loss(id, engine);
+        }
+        status_code(engine.status())
+    })
+}
+
+// deepest search suggest_move will run, to keep the page responsive
+const MAX_SUGGEST_DEPTH: i32 = 12;
+
+// Returns the packed move the computer would play, searching `depth` plies,
+// or -1 when there is none
+#[no_mangle]
+pub extern "C" fn suggest_move(id: i32, depth: i32) -> i32 {
+    let depth = depth.clamp(1, MAX_SUGGEST_DEPTH) as u32;
+    with_game(id, -1, |engine| {
+        search::best_move(engine, depth).map_or(-1, |r| pack_move(&r.best))
+    })
+}
+
+// Reserves `len` bytes of linear memory for the host to write a string
+// into, e.g. a position for load_position
+#[no_mangle]
+pub extern "C" fn alloc_buffer(len: i32) -> *mut u8 {
+    let mut buffer: Vec<u8> = Vec::with_capacity(len.max(0) as usize);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// # Safety
+/// `ptr` and `len` must come from a single call to alloc_buffer
+#[no_mangle]
+pub unsafe extern "C" fn free_buffer(ptr: *mut u8, len: i32) {
+    drop(Vec::from_raw_parts(ptr, 0, len.max(0) as usize));
+}
+
+// Sets up a game's board from a position string (see `pdn::parse_fen`) of
+// `len` bytes at `ptr`, keeping its rules. Returns 1 when loaded and 0 for
+// an invalid position or unknown game; the host should redraw the board
+// from get_piece.
+/// # Safety
+/// `ptr` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn load_position(id: i32, ptr: *const u8, len: i32) -> i32 {
+    if ptr.is_null() || len < 0 {
+        return 0;
+    }
+    let bytes = std::slice::from_raw_parts(ptr, len as usize);
+    with_game_mut(id, 0, |engine| {
+        match std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|p| GameEngine::from_position_with(*engine.variant(), p).ok())
+        {
+            Some(loaded) => {
+                *engine = loaded;
+                1
             }
-            1
+            None => 0,
         }
+    })
+}
+
+// Takes back the last move. Returns 0 when there is nothing to undo and 1
+// otherwise; pieces reappear and lose their crowns, so the host should
+// redraw the board from get_piece.
+#[no_mangle]
+pub extern "C" fn undo_move(id: i32) -> i32 {
+    with_game_mut(id, 0, |engine| match engine.undo() {
+        Ok(_) => 1,
         Err(_) => 0,
+    })
+}
+
+// Replays the last move taken back, with the same results as move_piece
+#[no_mangle]
+pub extern "C" fn redo_move(id: i32) -> i32 {
+    with_game_mut(id, 0, |engine| match engine.redo() {
+        Ok(mr) => notify_move(id, engine, &mr),
+        Err(_) => 0,
+    })
+}
+
+// Like redo_move for a game on a clock, charging the replayed move to the
+// mover at `now`, with the same results as move_piece_at
+#[no_mangle]
+pub extern "C" fn redo_move_at(id: i32, now: f64) -> i32 {
+    with_game_mut(id, 0, |engine| {
+        let running = engine.status() == GameStatus::InProgress;
+        match engine.redo_at(millis(now)) {
+            Ok(mr) => notify_move(id, engine, &mr),
+            Err(GameError::OutOfTime) if running => {
+                notify_time_loss(id, engine);
+                3
+            }
+            Err(_) => 0,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn get_history_length(id: i32) -> i32 {
+    with_game(id, 0, |engine| engine.history().len() as i32)
+}
+
+// Returns the packed move at `index` of the game so far, or -1
+#[no_mangle]
+pub extern "C" fn get_history_move(id: i32, index: i32) -> i32 {
+    with_game(id, -1, |engine| {
+        let moves: Vec<Move> = engine.history().iter().map(|h| h.mv).collect();
+        packed_move_at(&moves, index)
+    })
+}
+
+fn notify_time_loss(id: i32, engine: &GameEngine) {
+    unsafe {
+        notify_gameover(id, status_code(engine.status()));
+    }
+}
+
+fn notify_move(id: i32, engine: &GameEngine, mr: &MoveResult) -> i32 {
+    let Coordinate(fx, fy) = mr.mv.from;
+    let Coordinate(tx, ty) = mr.mv.to;
+    unsafe {
+        notify_piecemoved(id, fx as i32, fy as i32, tx as i32, ty as i32);
     }
-}
\ No newline at end of file
+    if mr.crowned {
+        unsafe {
+            notify_piececrowned(id, tx as i32, ty as i32);
+        }
+    }
+    if engine.status() != GameStatus::InProgress {
+        unsafe {
+            notify_gameover(id, status_code(engine.status()));
+        }
+    }
+    if mr.turn_in_progress {
+        2
+    } else {
+        1
+    }
+}
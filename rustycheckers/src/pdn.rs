@@ -0,0 +1,532 @@
+use super::bitboard::{bit_coordinate, square_bit, Bitboard};
+use super::board::{Coordinate, GamePiece, Move, PieceColor};
+use super::game::{DrawReason, GameEngine, GameStatus};
+use super::variant::Variant;
+use std::fmt;
+
+// Portable Draughts Notation. Squares are numbered from 1 (32 on an 8x8
+// board, 50 on a 10x10 one) from Black's back rank, which is the top row on
+// our board, with square 1 in the double corner.
+
+#[derive(Debug, PartialEq)]
+pub enum PdnError {
+    Syntax(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for PdnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PdnError::Syntax(s) => write!(f, "invalid PDN: {}", s),
+            PdnError::IllegalMove(s) => write!(f, "illegal move {}", s),
+        }
+    }
+}
+
+/// One player's turn as written in PDN: the squares the piece stops on,
+/// e.g. `11-15` or `9x18x27`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdnMove {
+    pub squares: Vec<u8>,
+    pub capture: bool,
+}
+
+/// A parsed game: its tag pairs, the moves in order and the result
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pdn {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PdnMove>,
+    pub result: String,
+}
+
+const RESULTS: [&str; 7] = ["1-0", "0-1", "1/2-1/2", "2-0", "0-2", "1-1", "*"];
+
+// the most squares any supported board has
+const MAX_SQUARE: u8 = 50;
+
+pub fn square_to_coordinate(square: u8, size: usize) -> Option<Coordinate> {
+    if square < 1 || square as usize > size * size / 2 {
+        return None;
+    }
+    Some(bit_coordinate(square as u32 - 1, size))
+}
+
+pub fn coordinate_to_square(coord: Coordinate, size: usize) -> Option<u8> {
+    square_bit(coord, size).map(|bit| bit as u8 + 1)
+}
+
+impl fmt::Display for PdnMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sep = if self.capture { "x" } else { "-" };
+        let squares: Vec<String> = self.squares.iter().map(|s| s.to_string()).collect();
+        write!(f, "{}", squares.join(sep))
+    }
+}
+
+impl Pdn {
+    pub fn parse(text: &str) -> Result<Pdn, PdnError> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                tags.push(parse_tag(line)?);
+            } else {
+                movetext.push_str(line);
+                movetext.push(' ');
+            }
+        }
+
+        let mut moves = Vec::new();
+        let mut result = "*".to_string();
+        for token in strip_annotations(&movetext)?.split_whitespace() {
+            if RESULTS.contains(&token) {
+                result = token.to_string();
+                continue;
+            }
+            // move numbers may be written apart ("12.") or attached ("12.11-15")
+            let token = match token.rfind('.') {
+                Some(i) => &token[i + 1..],
+                None => token,
+            };
+            let token = token.trim_end_matches(['!', '?']);
+            if !token.is_empty() {
+                moves.push(parse_move(token)?);
+            }
+        }
+
+        Ok(Pdn {
+            tags,
+            moves,
+            result,
+        })
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // The rules named by the GameType tag, American when there is none
+    pub fn variant(&self) -> Result<Variant, PdnError> {
+        let game_type = match self.tag("GameType") {
+            Some(t) => t,
+            None => return Ok(Variant::american()),
+        };
+        // only the number matters, the rest describes the board
+        game_type
+            .split(',')
+            .next()
+            .and_then(|n| n.trim().parse().ok())
+            .and_then(Variant::from_game_type)
+            .ok_or_else(|| PdnError::Syntax(format!("unsupported GameType {}", game_type)))
+    }
+
+    // Plays the moves from the starting position, or the FEN tag's
+    // position when there is one
+    pub fn replay(&self) -> Result<GameEngine, PdnError> {
+        let variant = self.variant()?;
+        let mut engine = match self.tag("FEN") {
+            Some(fen) => GameEngine::from_position_with(variant, fen)?,
+            None => GameEngine::with_variant(variant),
+        };
+        for pdn_move in self.moves.iter() {
+            play(&mut engine, pdn_move)?;
+        }
+        Ok(engine)
+    }
+
+    // Writes out a game's history, every jump of a capture spelled out. A
+    // game under other rules than American gets a GameType tag, and one
+    // that was set up a FEN tag with its starting position.
+    pub fn from_game(engine: &GameEngine, mut tags: Vec<(String, String)>) -> Pdn {
+        let variant = *engine.variant();
+        let has_tag = |tags: &[(String, String)], name: &str| tags.iter().any(|(n, _)| n == name);
+        if variant != Variant::american() && !has_tag(&tags, "GameType") {
+            tags.push(("GameType".to_string(), variant.game_type.to_string()));
+        }
+        let start = engine.start_position();
+        if start != GameEngine::with_variant(variant).to_position() && !has_tag(&tags, "FEN") {
+            tags.push(("FEN".to_string(), start));
+        }
+        let size = variant.size;
+        let mut moves = Vec::new();
+        let mut current: Option<PdnMove> = None;
+        for entry in engine.history() {
+            let mut pdn_move = current.take().unwrap_or_else(|| PdnMove {
+                squares: vec![coordinate_to_square(entry.mv.from, size).unwrap()],
+                capture: entry.captured.is_some(),
+            });
+            pdn_move
+                .squares
+                .push(coordinate_to_square(entry.mv.to, size).unwrap());
+            if entry.turn_passed {
+                moves.push(pdn_move);
+            } else {
+                current = Some(pdn_move);
+            }
+        }
+        // an unfinished capture is still worth keeping
+        moves.extend(current);
+
+        let result = match engine.status() {
+            GameStatus::InProgress => "*",
+            // results are given from the first player's side
+            GameStatus::Won(color) | GameStatus::WonOnTime(color)
+                if color == variant.first_to_move =>
+            {
+                "1-0"
+            }
+            GameStatus::Won(_) | GameStatus::WonOnTime(_) => "0-1",
+            GameStatus::Draw(DrawReason::Repetition) | GameStatus::Draw(DrawReason::MoveLimit) => {
+                "1/2-1/2"
+            }
+        };
+        Pdn {
+            tags,
+            moves,
+            result: result.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Pdn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in self.tags.iter() {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('"', "\\\""))?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, pdn_move) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                write!(f, "{}. ", i / 2 + 1)?;
+            }
+            write!(f, "{} ", pdn_move)?;
+        }
+        writeln!(f, "{}", self.result)
+    }
+}
+
+// Position strings are PDN's FEN: the side to move, then each side's
+// pieces by square with kings marked K, e.g. `B:W18,24,K27:B12,16,K22`.
+// Ranges of squares like `W21-32` are accepted too. `size` is the board's,
+// 8 or 10.
+pub fn parse_fen(fen: &str, size: usize) -> Result<(Bitboard, PieceColor), PdnError> {
+    let invalid = |why: &str| PdnError::Syntax(format!("bad position {}: {}", fen, why));
+    let mut fields = fen.trim().trim_end_matches('.').split(':');
+    let turn = match fields.next() {
+        Some("B") => PieceColor::Black,
+        Some("W") => PieceColor::White,
+        _ => return Err(invalid("no side to move")),
+    };
+
+    let mut board = Bitboard::new(size);
+    for field in fields {
+        let color = match field.get(..1) {
+            Some("B") => PieceColor::Black,
+            Some("W") => PieceColor::White,
+            _ => return Err(invalid("pieces must be listed for B or W")),
+        };
+        for item in field[1..].split(',').filter(|i| !i.is_empty()) {
+            let (crowned, squares) = match item.strip_prefix('K') {
+                Some(rest) => (true, rest),
+                None => (false, item),
+            };
+            let mut bounds = squares.splitn(2, '-').map(|n| n.parse::<u8>().ok());
+            let first = bounds.next().flatten().ok_or_else(|| invalid(item))?;
+            let last = match bounds.next() {
+                Some(n) => n.ok_or_else(|| invalid(item))?,
+                None => first,
+            };
+            if first > last {
+                return Err(invalid(item));
+            }
+            for square in first..=last {
+                let coord = square_to_coordinate(square, size).ok_or_else(|| invalid(item))?;
+                if board.get(coord).is_some() {
+                    return Err(invalid("square listed twice"));
+                }
+                let man = GamePiece::new(color);
+                board.set(
+                    coord,
+                    Some(if crowned {
+                        GamePiece::crowned(man)
+                    } else {
+                        man
+                    }),
+                );
+            }
+        }
+    }
+    Ok((board, turn))
+}
+
+pub fn write_fen(board: &Bitboard, turn: PieceColor) -> String {
+    let side = |color: PieceColor| {
+        let squares = board.size() * board.size() / 2;
+        let pieces: Vec<String> = (1..=squares as u8)
+            .filter_map(|square| {
+                board
+                    .piece_at(square as u32 - 1)
+                    .filter(|p| p.color == color)
+                    .map(|p| format!("{}{}", if p.crowned { "K" } else { "" }, square))
+            })
+            .collect();
+        pieces.join(",")
+    };
+    let turn = match turn {
+        PieceColor::Black => "B",
+        PieceColor::White => "W",
+    };
+    format!(
+        "{}:W{}:B{}",
+        turn,
+        side(PieceColor::White),
+        side(PieceColor::Black)
+    )
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), PdnError> {
+    let invalid = || PdnError::Syntax(format!("bad tag {}", line));
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(invalid)?
+        .trim();
+    let (name, value) = inner.split_at(inner.find(char::is_whitespace).ok_or_else(invalid)?);
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+    Ok((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+// Drops comments, variations and numeric annotations from move text
+fn strip_annotations(movetext: &str) -> Result<String, PdnError> {
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut in_comment = false;
+    let mut chars = movetext.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' if in_comment => in_comment = false,
+            _ if in_comment => {}
+            '{' => in_comment = true,
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return Err(PdnError::Syntax("unbalanced variation".to_string())),
+            _ if depth > 0 => {}
+            '$' => {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    if in_comment || depth > 0 {
+        return Err(PdnError::Syntax(
+            "unterminated comment or variation".to_string(),
+        ));
+    }
+    Ok(out)
+}
+
+fn parse_move(token: &str) -> Result<PdnMove, PdnError> {
+    let capture = token.contains('x');
+    let squares = token
+        .split(if capture { 'x' } else { '-' })
+        .map(|s| {
+            s.parse::<u8>()
+                .ok()
+                .filter(|n| (1..=MAX_SQUARE).contains(n))
+        })
+        .collect::<Option<Vec<u8>>>()
+        .filter(|s| s.len() >= 2 && (capture || s.len() == 2))
+        .ok_or_else(|| PdnError::Syntax(format!("bad move {}", token)))?;
+    Ok(PdnMove { squares, capture })
+}
+
+fn play(engine: &mut GameEngine, pdn_move: &PdnMove) -> Result<(), PdnError> {
+    let illegal = || PdnError::IllegalMove(pdn_move.to_string());
+    let size = engine.variant().size;
+    let stops: Vec<Coordinate> = pdn_move
+        .squares
+        .iter()
+        .map(|s| square_to_coordinate(*s, size))
+        .collect::<Option<_>>()
+        .ok_or_else(illegal)?;
+
+    if !pdn_move.capture {
+        let mv = Move {
+            from: stops[0],
+            to: stops[1],
+        };
+        return match engine.move_piece(&mv) {
+            Ok(ref r) if r.captured.is_none() => Ok(()),
+            Ok(_) => Err(illegal()),
+            Err(_) => Err(illegal()),
+        };
+    }
+
+    if jump_through(engine, stops[0], &stops[1..]) {
+        Ok(())
+    } else {
+        Err(illegal())
+    }
+}
+
+// Short notation only names some of the squares a multi-jump stops on, so
+// search for a sequence of jumps that passes through all of them in order
+// and ends the turn. Dead ends are taken back with undo.
+fn jump_through(engine: &mut GameEngine, from: Coordinate, stops: &[Coordinate]) -> bool {
+    for mv in engine.valid_moves_from(from) {
+        let result = match engine.move_piece(&mv) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        if result.captured.is_some() {
+            let remaining = if mv.to == stops[0] {
+                &stops[1..]
+            } else {
+                stops
+            };
+            let done = if result.turn_in_progress {
+                !remaining.is_empty() && jump_through(engine, mv.to, remaining)
+            } else {
+                remaining.is_empty()
+            };
+            if done {
+                return true;
+            }
+        }
+        engine.undo().unwrap();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GAME: &str = r#"[Event "Test"]
+[Black "A"]
+[White "B"]
+
+1. 11-15 22-18 {an early exchange} 2. 15x22 25x18 (2... 26x17) 3.12-16! *"#;
+
+    #[test]
+    fn squares_map_to_the_board() {
+        for square in 1..=32 {
+            let coord = square_to_coordinate(square, 8).unwrap();
+            assert_eq!(coordinate_to_square(coord, 8), Some(square));
+        }
+        // Black's men start on 1 to 12
+        assert_eq!(square_to_coordinate(1, 8), Some(Coordinate(6, 7)));
+        assert_eq!(square_to_coordinate(12, 8), Some(Coordinate(0, 5)));
+        assert_eq!(square_to_coordinate(32, 8), Some(Coordinate(1, 0)));
+        assert_eq!(square_to_coordinate(33, 8), None);
+        assert_eq!(coordinate_to_square(Coordinate(0, 0), 8), None);
+        // and on 10x10 square 46 is White's corner
+        assert_eq!(square_to_coordinate(46, 10), Some(Coordinate(9, 0)));
+        assert_eq!(square_to_coordinate(51, 10), None);
+    }
+
+    #[test]
+    fn parses_and_replays_a_game() {
+        let pdn = Pdn::parse(GAME).unwrap();
+        assert_eq!(pdn.tag("Black"), Some("A"));
+        assert_eq!(pdn.moves.len(), 5);
+        assert_eq!(
+            pdn.moves[2],
+            PdnMove {
+                squares: vec![15, 22],
+                capture: true
+            }
+        );
+
+        let engine = pdn.replay().unwrap();
+        assert_eq!(engine.history().len(), 5);
+        let written = Pdn::from_game(&engine, pdn.tags.clone());
+        assert_eq!(written.moves, pdn.moves);
+        assert_eq!(Pdn::parse(&written.to_string()).unwrap(), written);
+    }
+
+    #[test]
+    fn refuses_illegal_moves() {
+        assert_eq!(
+            Pdn::parse("1. 11-19").unwrap().replay().err(),
+            Some(PdnError::IllegalMove("11-19".to_string()))
+        );
+        // the capture is mandatory
+        assert!(Pdn::parse("1. 11-15 22-18 2. 10-14")
+            .unwrap()
+            .replay()
+            .is_err());
+        assert!(Pdn::parse("1. 11-15 22-18 2. 15-22")
+            .unwrap()
+            .replay()
+            .is_err());
+        assert!(Pdn::parse("1. 11-99").is_err());
+        assert!(Pdn::parse("1. 11-45").unwrap().replay().is_err());
+    }
+
+    #[test]
+    fn set_up_games_keep_their_position() {
+        let fen = "W:WK14:B9,K23";
+        let pdn = Pdn::parse(&format!("[FEN \"{}\"]\n1. 14x5", fen)).unwrap();
+        let engine = pdn.replay().unwrap();
+        assert_eq!(engine.status(), GameStatus::InProgress);
+        let written = Pdn::from_game(&engine, Vec::new());
+        assert_eq!(written.tag("FEN"), Some(fen));
+        assert_eq!(written.moves, pdn.moves);
+    }
+
+    #[test]
+    fn expands_short_multi_jumps() {
+        let short = "1. 9-14 22-18 2. 11-16 18x9 3. 6x13 23-19 4. 16x23 27x18 \
+                     5. 5-9 31-27 6. 13-17 21x5";
+        let engine = Pdn::parse(short).unwrap().replay().unwrap();
+        let written = Pdn::from_game(&engine, Vec::new());
+        assert_eq!(
+            written.moves.last(),
+            Some(&PdnMove {
+                squares: vec![21, 14, 5],
+                capture: true
+            })
+        );
+        assert_eq!(
+            Pdn::parse(&written.to_string())
+                .unwrap()
+                .replay()
+                .unwrap()
+                .history(),
+            engine.history()
+        );
+
+        // a jump has to go through every square named
+        assert!(Pdn::parse(&short.replace("21x5", "21x17x5"))
+            .unwrap()
+            .replay()
+            .is_err());
+    }
+
+    #[test]
+    fn game_type_picks_the_rules() {
+        let pdn = Pdn::parse("[GameType \"20\"]\n1. 32-28 19-23 2. 28x19 14x23").unwrap();
+        assert_eq!(pdn.variant(), Ok(Variant::international()));
+        let engine = pdn.replay().unwrap();
+        assert_eq!(engine.history().len(), 4);
+        assert_eq!(engine.current_turn(), PieceColor::White);
+
+        let written = Pdn::from_game(&engine, Vec::new());
+        assert_eq!(written.tag("GameType"), Some("20"));
+        assert_eq!(written.tag("FEN"), None);
+        assert_eq!(written.moves, pdn.moves);
+
+        assert!(Pdn::parse("[GameType \"26\"]").unwrap().replay().is_err());
+    }
+}
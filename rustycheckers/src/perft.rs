@@ -0,0 +1,104 @@
+use super::board::Move;
+use super::game::GameEngine;
+
+// Counts the positions reached after `depth` turns. A multi-jump is one
+// turn however many jumps it takes, matching the published perft numbers.
+pub fn perft(engine: &GameEngine, depth: u32) -> u64 {
+    let mut engine = engine.clone();
+    count(&mut engine, depth)
+}
+
+// The perft count under each first step, for tracking down where a
+// generator goes wrong
+pub fn divide(engine: &GameEngine, depth: u32) -> Vec<(Move, u64)> {
+    let mut engine = engine.clone();
+    if depth == 0 {
+        return Vec::new();
+    }
+    engine
+        .legal_moves()
+        .into_iter()
+        .map(|mv| {
+            let nodes = count_after(&mut engine, &mv, depth);
+            (mv, nodes)
+        })
+        .collect()
+}
+
+fn count(engine: &mut GameEngine, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    engine
+        .legal_moves()
+        .iter()
+        .map(|mv| count_after(engine, mv, depth))
+        .sum()
+}
+
+fn count_after(engine: &mut GameEngine, mv: &Move, depth: u32) -> u64 {
+    let result = engine.move_piece(mv).unwrap();
+    // the rest of a multi-jump belongs to the same turn
+    let nodes = if result.turn_in_progress {
+        count(engine, depth)
+    } else {
+        count(engine, depth - 1)
+    };
+    engine.undo().unwrap();
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::variant::Variant;
+    use super::*;
+
+    // American checkers from the starting position
+    const START_COUNTS: [u64; 7] = [7, 49, 302, 1469, 7361, 36768, 179740];
+
+    #[test]
+    fn start_position_counts() {
+        let engine = GameEngine::new();
+        assert_eq!(perft(&engine, 0), 1);
+        for (depth, expected) in START_COUNTS.iter().enumerate().take(6) {
+            assert_eq!(
+                perft(&engine, depth as u32 + 1),
+                *expected,
+                "depth {}",
+                depth + 1
+            );
+        }
+    }
+
+    #[test]
+    #[ignore] // slow in debug builds
+    fn start_position_deep() {
+        assert_eq!(perft(&GameEngine::new(), 7), START_COUNTS[6]);
+    }
+
+    #[test]
+    fn variant_start_counts() {
+        // both published, flying kings and capture-maximum included
+        let international = GameEngine::with_variant(Variant::international());
+        for (depth, expected) in [9, 81, 658, 4265].iter().enumerate() {
+            assert_eq!(perft(&international, depth as u32 + 1), *expected);
+        }
+        let russian = GameEngine::with_variant(Variant::russian());
+        assert_eq!(perft(&russian, 5), 7482);
+    }
+
+    #[test]
+    fn divide_adds_up() {
+        let engine = GameEngine::new();
+        let split = divide(&engine, 3);
+        assert_eq!(split.len(), 7);
+        assert_eq!(split.iter().map(|(_, n)| n).sum::<u64>(), perft(&engine, 3));
+    }
+
+    #[test]
+    fn multi_jumps_count_once() {
+        // White's man at 24 can take 19 then 10, a single turn
+        let engine = GameEngine::from_position("W:W24:B19,10").unwrap();
+        assert_eq!(perft(&engine, 1), 1);
+    }
+}
@@ -0,0 +1,358 @@
+use super::board::{Coordinate, Move, PieceColor};
+use super::game::{GameEngine, GameStatus};
+use super::transposition::{Bound, Entry, TranspositionTable};
+
+// Scores are in hundredths of a man, from the side to move's point of view
+const MAN: i32 = 100;
+const KING: i32 = 150;
+const ADVANCE: i32 = 2;
+const MOBILITY: i32 = 3;
+const WIN: i32 = 1_000_000;
+// scores this close to WIN are wins found by the search
+const WIN_HORIZON: i32 = 10_000;
+
+// how often, in nodes, the clock is checked
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Asked every so often during a search whether time is up. The caller
+/// supplies the clock, since `std::time::Instant` panics on
+/// wasm32-unknown-unknown.
+pub type OutOfTime<'a> = &'a mut dyn FnMut() -> bool;
+
+/// The move a search settled on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchResult {
+    pub best: Move,
+    pub score: i32,
+    // deepest iteration that finished, 0 if time ran out during the first
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+// Searches `depth` plies ahead. Every jump of a multi-jump is a ply, so the
+// move returned may be the first of several for the same piece.
+pub fn best_move(engine: &GameEngine, depth: u32) -> Option<SearchResult> {
+    search(engine, depth, None, &mut TranspositionTable::default())
+}
+
+// Deepens until `out_of_time` says to stop, then returns the last finished
+// result. Natively that can be `|| Instant::now() >= deadline`; in a
+// browser, a check against `performance.now()`.
+pub fn best_move_within<F: FnMut() -> bool>(
+    engine: &GameEngine,
+    max_depth: u32,
+    mut out_of_time: F,
+) -> Option<SearchResult> {
+    search(
+        engine,
+        max_depth,
+        Some(&mut out_of_time),
+        &mut TranspositionTable::default(),
+    )
+}
+
+// Searches with a table kept by the caller, so results carry over from one
+// move to the next
+pub fn search<'a>(
+    engine: &GameEngine,
+    max_depth: u32,
+    out_of_time: Option<OutOfTime<'a>>,
+    table: &'a mut TranspositionTable,
+) -> Option<SearchResult> {
+    Searcher::new(out_of_time, table).run(engine, max_depth)
+}
+
+// Material, kings, advancement of men and mobility for the side to move
+pub fn evaluate(engine: &GameEngine) -> i32 {
+    let side = engine.current_turn();
+    let size = engine.variant().size;
+    let mut score = 0;
+    for x in 0..size {
+        for y in 0..size {
+            if let Ok(Some(piece)) = engine.get_piece(Coordinate(x, y)) {
+                let value = if piece.crowned {
+                    KING
+                } else {
+                    // Black men advance toward row 0, White men toward the
+                    // last row
+                    let advanced = match piece.color {
+                        PieceColor::Black => size - 1 - y,
+                        PieceColor::White => y,
+                    };
+                    MAN + ADVANCE * advanced as i32
+                };
+                score += if piece.color == side { value } else { -value };
+            }
+        }
+    }
+    let mobility = engine.mobility(side) as i32 - engine.mobility(side.opponent()) as i32;
+    score + MOBILITY * mobility
+}
+
+// Wins are stored relative to the position rather than the root
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > WIN - WIN_HORIZON {
+        score + ply
+    } else if score < -(WIN - WIN_HORIZON) {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > WIN - WIN_HORIZON {
+        score - ply
+    } else if score < -(WIN - WIN_HORIZON) {
+        score + ply
+    } else {
+        score
+    }
+}
+
+struct Searcher<'a> {
+    out_of_time: Option<OutOfTime<'a>>,
+    nodes: u64,
+    stopped: bool,
+    table: &'a mut TranspositionTable,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(out_of_time: Option<OutOfTime<'a>>, table: &'a mut TranspositionTable) -> Searcher<'a> {
+        Searcher {
+            out_of_time,
+            nodes: 0,
+            stopped: false,
+            table,
+        }
+    }
+
+    // Iterative deepening, searching the previous best move first
+    fn run(&mut self, engine: &GameEngine, max_depth: u32) -> Option<SearchResult> {
+        // the search makes and takes back moves on its own copy
+        let mut engine = engine.clone();
+        let mut moves = engine.legal_moves();
+        let mut result = None;
+
+        for depth in 1..=max_depth.max(1) {
+            let mut best: Option<(Move, i32)> = None;
+            let mut alpha = -WIN - 1;
+            for mv in moves.iter() {
+                self.check_clock();
+                if self.stopped {
+                    break;
+                }
+                let score = self.score_move(&mut engine, mv, depth, alpha, WIN + 1, 0);
+                if self.stopped {
+                    break;
+                }
+                let better = match best {
+                    Some((_, b)) => score > b,
+                    None => true,
+                };
+                if better {
+                    best = Some((*mv, score));
+                    alpha = alpha.max(score);
+                }
+            }
+            if self.stopped {
+                // out of time before any depth finished: play the best move
+                // scored so far, or failing that the first legal one
+                if result.is_none() {
+                    let (best, score) = best.unwrap_or((moves[0], 0));
+                    result = Some(SearchResult {
+                        best,
+                        score,
+                        depth: 0,
+                        nodes: self.nodes,
+                    });
+                }
+                break;
+            }
+            if let Some((mv, score)) = best {
+                result = Some(SearchResult {
+                    best: mv,
+                    score,
+                    depth,
+                    nodes: self.nodes,
+                });
+                moves.retain(|m| *m != mv);
+                moves.insert(0, mv);
+                // nothing deeper changes a forced result
+                if score.abs() >= WIN - max_depth as i32 {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    fn check_clock(&mut self) {
+        if let Some(ref mut out_of_time) = self.out_of_time {
+            self.stopped = self.stopped || out_of_time();
+        }
+    }
+
+    // Scores a move for the side making it
+    fn score_move(
+        &mut self,
+        engine: &mut GameEngine,
+        mv: &Move,
+        depth: u32,
+        alpha: i32,
+        beta: i32,
+        ply: i32,
+    ) -> i32 {
+        let side = engine.current_turn();
+        let result = engine.move_piece(mv).unwrap();
+        // a multi-jump continues with the same side to move
+        let score = if result.turn_in_progress {
+            self.negamax(engine, depth - 1, alpha, beta, ply + 1)
+        } else {
+            debug_assert!(engine.current_turn() != side);
+            -self.negamax(engine, depth - 1, -beta, -alpha, ply + 1)
+        };
+        engine.undo().unwrap();
+        score
+    }
+
+    fn negamax(
+        &mut self,
+        engine: &mut GameEngine,
+        depth: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        ply: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CLOCK_INTERVAL) {
+            self.check_clock();
+        }
+        if self.stopped {
+            return 0;
+        }
+
+        match engine.status() {
+            // quicker wins score higher
+            GameStatus::Won(color) | GameStatus::WonOnTime(color)
+                if color == engine.current_turn() =>
+            {
+                return WIN - ply
+            }
+            GameStatus::Won(_) | GameStatus::WonOnTime(_) => return -(WIN - ply),
+            GameStatus::Draw(_) => return 0,
+            GameStatus::InProgress => {}
+        }
+        if depth == 0 {
+            return evaluate(engine);
+        }
+
+        let hash = engine.hash();
+        let original_alpha = alpha;
+        let mut moves = engine.legal_moves();
+        if let Some(entry) = self.table.probe(hash).copied() {
+            if entry.depth >= depth {
+                let score = from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower => alpha = alpha.max(score),
+                    Bound::Upper => beta = beta.min(score),
+                }
+                if alpha >= beta {
+                    return score;
+                }
+            }
+            // try the move that was best last time first
+            if let Some(i) = entry.best.and_then(|b| moves.iter().position(|m| *m == b)) {
+                moves.swap(0, i);
+            }
+        }
+
+        let mut best = -WIN - 1;
+        let mut best_move = None;
+        for mv in moves {
+            let score = self.score_move(engine, &mv, depth, alpha, beta, ply);
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if !self.stopped {
+            let bound = if best <= original_alpha {
+                Bound::Upper
+            } else if best >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.table.store(Entry {
+                hash,
+                depth,
+                score: to_table(best, ply),
+                bound,
+                best: best_move,
+            });
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggests_a_legal_move() {
+        let engine = GameEngine::new();
+        let result = best_move(&engine, 4).unwrap();
+        assert!(engine.legal_moves().contains(&result.best));
+        assert_eq!(result.depth, 4);
+        assert_eq!(
+            best_move(&GameEngine::from_position("B:W1:B").unwrap(), 4),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_a_blocking_win() {
+        // the White man is stuck once the Black king takes its only square
+        let engine = GameEngine::from_position("B:W5:BK6").unwrap();
+        let result = best_move(&engine, 3).unwrap();
+        assert_eq!(result.best, Move::new((5, 6), (6, 7)));
+        assert!(result.score >= WIN - 1);
+    }
+
+    #[test]
+    fn table_results_carry_over() {
+        let engine = GameEngine::new();
+        let mut table = TranspositionTable::new(12);
+        let first = search(&engine, 5, None, &mut table).unwrap();
+        let again = search(&engine, 5, None, &mut table).unwrap();
+        assert_eq!(first.best, again.best);
+        assert_eq!(first.score, again.score);
+        assert!(again.nodes < first.nodes);
+    }
+
+    #[test]
+    fn stops_when_time_runs_out() {
+        use std::time::{Duration, Instant};
+
+        let engine = GameEngine::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let result = best_move_within(&engine, 64, || Instant::now() >= deadline).unwrap();
+        assert!(result.depth < 64);
+    }
+
+    #[test]
+    fn falls_back_to_a_legal_move() {
+        let engine = GameEngine::new();
+        let result = best_move_within(&engine, 8, || true).unwrap();
+        assert_eq!(result.depth, 0);
+        assert_eq!(result.best, engine.legal_moves()[0]);
+    }
+}
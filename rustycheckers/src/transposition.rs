@@ -0,0 +1,105 @@
+use super::board::Move;
+
+/// How a stored score relates to the true score of a position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    // the search failed high, the score is at least this
+    Lower,
+    // the search failed low, the score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<Move>,
+}
+
+/// A fixed size table of search results keyed by Zobrist hash. Each hash
+/// maps to one slot; a deeper result replaces a shallower one, and any
+/// result replaces one for a different position.
+pub struct TranspositionTable {
+    slots: Vec<Option<Entry>>,
+    mask: usize,
+}
+
+// 64Ki entries
+pub const DEFAULT_TABLE_BITS: u32 = 16;
+
+impl TranspositionTable {
+    // Holds 2^bits entries
+    pub fn new(bits: u32) -> TranspositionTable {
+        let size = 1 << bits;
+        TranspositionTable {
+            slots: vec![None; size],
+            mask: size - 1,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<&Entry> {
+        self.slots[hash as usize & self.mask]
+            .as_ref()
+            .filter(|e| e.hash == hash)
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        let slot = &mut self.slots[entry.hash as usize & self.mask];
+        let replace = match slot {
+            Some(old) => old.hash != entry.hash || entry.depth >= old.depth,
+            None => true,
+        };
+        if replace {
+            *slot = Some(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|s| *s = None);
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> TranspositionTable {
+        TranspositionTable::new(DEFAULT_TABLE_BITS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(hash: u64, depth: u32) -> Entry {
+        Entry {
+            hash,
+            depth,
+            score: depth as i32,
+            bound: Bound::Exact,
+            best: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_deepest_result() {
+        let mut table = TranspositionTable::new(4);
+        assert_eq!(table.capacity(), 16);
+        table.store(entry(3, 5));
+        table.store(entry(3, 2));
+        assert_eq!(table.probe(3).map(|e| e.depth), Some(5));
+
+        // 19 shares a slot with 3 and pushes it out
+        table.store(entry(19, 1));
+        assert_eq!(table.probe(3), None);
+        assert_eq!(table.probe(19).map(|e| e.depth), Some(1));
+
+        table.clear();
+        assert_eq!(table.probe(19), None);
+    }
+}
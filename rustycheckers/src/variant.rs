@@ -0,0 +1,129 @@
+use super::board::PieceColor;
+
+/// Which captures a player may choose between
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureRule {
+    // any capture, as long as it is carried through to the end
+    Free,
+    // only a sequence taking the most pieces
+    Maximum,
+    // the most pieces, then capturing with a king, then taking the most
+    // kings, then taking a king soonest
+    Italian,
+}
+
+/// What happens when a man reaches the far row part way through a capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crowning {
+    // it is crowned and the turn ends
+    EndsTurn,
+    // it is crowned and carries on capturing as a king
+    ContinuesAsKing,
+    // it carries on as a man and is only crowned if it finishes there
+    OnlyAtEnd,
+}
+
+/// The rules a game is played under
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variant {
+    pub name: &'static str,
+    // squares along each side
+    pub size: usize,
+    // rows of men each side starts with
+    pub rows_of_men: usize,
+    pub first_to_move: PieceColor,
+    // kings move and capture any distance along a diagonal
+    pub flying_kings: bool,
+    pub men_capture_backwards: bool,
+    pub men_capture_kings: bool,
+    pub capture_rule: CaptureRule,
+    pub crowning: Crowning,
+    // the PDN GameType tag value
+    pub game_type: u8,
+}
+
+impl Variant {
+    // English draughts, the rules the engine started with
+    pub fn american() -> Variant {
+        Variant {
+            name: "American",
+            size: 8,
+            rows_of_men: 3,
+            first_to_move: PieceColor::Black,
+            flying_kings: false,
+            men_capture_backwards: false,
+            men_capture_kings: true,
+            capture_rule: CaptureRule::Free,
+            crowning: Crowning::EndsTurn,
+            game_type: 21,
+        }
+    }
+
+    pub fn international() -> Variant {
+        Variant {
+            name: "International",
+            size: 10,
+            rows_of_men: 4,
+            first_to_move: PieceColor::White,
+            flying_kings: true,
+            men_capture_backwards: true,
+            men_capture_kings: true,
+            capture_rule: CaptureRule::Maximum,
+            crowning: Crowning::OnlyAtEnd,
+            game_type: 20,
+        }
+    }
+
+    pub fn russian() -> Variant {
+        Variant {
+            name: "Russian",
+            size: 8,
+            rows_of_men: 3,
+            first_to_move: PieceColor::White,
+            flying_kings: true,
+            men_capture_backwards: true,
+            men_capture_kings: true,
+            capture_rule: CaptureRule::Free,
+            crowning: Crowning::ContinuesAsKing,
+            game_type: 25,
+        }
+    }
+
+    pub fn italian() -> Variant {
+        Variant {
+            name: "Italian",
+            size: 8,
+            rows_of_men: 3,
+            first_to_move: PieceColor::White,
+            flying_kings: false,
+            men_capture_backwards: false,
+            men_capture_kings: false,
+            capture_rule: CaptureRule::Italian,
+            crowning: Crowning::EndsTurn,
+            game_type: 22,
+        }
+    }
+
+    pub fn from_game_type(game_type: u8) -> Option<Variant> {
+        [
+            Variant::american(),
+            Variant::international(),
+            Variant::russian(),
+            Variant::italian(),
+        ]
+        .iter()
+        .find(|v| v.game_type == game_type)
+        .copied()
+    }
+
+    // Dark squares, the only ones pieces stand on
+    pub fn squares(&self) -> usize {
+        self.size * self.size / 2
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Variant {
+        Variant::american()
+    }
+}
@@ -0,0 +1,67 @@
+use super::bitboard::Bitboard;
+use super::board::{Coordinate, GamePiece, PieceColor};
+
+// Zobrist keys: one per piece kind per dark square (numbered as bitboard
+// bits), one per square for a piece that is part way through a multi-jump,
+// and one for Black to move. They are generated at compile time so a
+// position hashes the same on every build.
+
+const SQUARES: usize = 64;
+const PIECE_KINDS: usize = 4;
+const JUMPING_OFFSET: usize = SQUARES * PIECE_KINDS;
+const BLACK_TO_MOVE: usize = JUMPING_OFFSET + SQUARES;
+const KEY_COUNT: usize = BLACK_TO_MOVE + 1;
+const SEED: u64 = 0x5eed_c4ec_4e25_0001;
+
+static KEYS: [u64; KEY_COUNT] = generate_keys();
+
+// splitmix64
+const fn generate_keys() -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = SEED;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+pub fn piece_key(square: u32, piece: GamePiece) -> u64 {
+    let kind = match (piece.color, piece.crowned) {
+        (PieceColor::Black, false) => 0,
+        (PieceColor::Black, true) => 1,
+        (PieceColor::White, false) => 2,
+        (PieceColor::White, true) => 3,
+    };
+    KEYS[square as usize * PIECE_KINDS + kind]
+}
+
+pub fn jumping_key(square: u32) -> u64 {
+    KEYS[JUMPING_OFFSET + square as usize]
+}
+
+pub fn turn_key(turn: PieceColor) -> u64 {
+    match turn {
+        PieceColor::Black => KEYS[BLACK_TO_MOVE],
+        PieceColor::White => 0,
+    }
+}
+
+// The full hash, which GameEngine otherwise keeps up to date move by move
+pub fn hash_position(board: &Bitboard, turn: PieceColor, jumping: Option<Coordinate>) -> u64 {
+    let mut hash = turn_key(turn);
+    for square in 0..board.size() * board.size() / 2 {
+        if let Some(piece) = board.piece_at(square as u32) {
+            hash ^= piece_key(square as u32, piece);
+        }
+    }
+    if let Some(square) = jumping.and_then(|c| board.bit(c)) {
+        hash ^= jumping_key(square);
+    }
+    hash
+}
@@ -0,0 +1,226 @@
+use botengine::{readlock, writelock, Combatant, GameState, Gameloop};
+use criterion::{criterion_group, criterion_main, Criterion};
+use nalgebra::{Rotation2, Vector2};
+use parity_wasm::elements::{
+    CodeSection, ExportEntry, ExportSection, External, Func, FuncBody, FunctionSection,
+    FunctionType, ImportEntry, ImportSection, Instruction, Instructions, Internal, Module, Section,
+    Type, TypeSection, ValueType,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use wasmi::{
+    Error as InterpreterError, Externals, FuncInstance, FuncRef, ImportsBuilder,
+    ModuleImportResolver, ModuleInstance, RuntimeArgs, RuntimeValue, Signature, Trap,
+};
+
+const BOTS: usize = 16;
+const CYCLES: u32 = 200;
+
+// The runtime's import indexes for the calls the bench bots make
+const SCAN_INDEX: usize = 0;
+const SPEED_INDEX: usize = 4;
+const LOCX_INDEX: usize = 5;
+
+// A bot that calls `scan`, `loc_x` and `speed` in a loop until stopped
+fn scanning_bot() -> Vec<u8> {
+    let i32_result = || Type::Function(FunctionType::new(vec![], Some(ValueType::I32)));
+    let import = |field: &str, ty| {
+        ImportEntry::new("env".to_string(), field.to_string(), External::Function(ty))
+    };
+    let code = vec![
+        Instruction::Loop(parity_wasm::elements::BlockType::NoResult),
+        Instruction::I32Const(90),
+        Instruction::I32Const(10),
+        Instruction::Call(0),
+        Instruction::Drop,
+        Instruction::Call(1),
+        Instruction::Drop,
+        Instruction::Call(2),
+        Instruction::Drop,
+        Instruction::Br(0),
+        Instruction::End,
+        Instruction::End,
+    ];
+    let module = Module::new(vec![
+        Section::Type(TypeSection::with_types(vec![
+            Type::Function(FunctionType::new(
+                vec![ValueType::I32, ValueType::I32],
+                Some(ValueType::I32),
+            )),
+            i32_result(),
+            Type::Function(FunctionType::new(vec![], None)),
+        ])),
+        Section::Import(ImportSection::with_entries(vec![
+            import("scan", 0),
+            import("loc_x", 1),
+            import("speed", 1),
+        ])),
+        Section::Function(FunctionSection::with_entries(vec![Func::new(2)])),
+        Section::Export(ExportSection::with_entries(vec![ExportEntry::new(
+            botengine::BOTINIT_NAME.to_string(),
+            Internal::Function(3),
+        )])),
+        Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+            vec![],
+            Instructions::new(code),
+        )])),
+    ]);
+    parity_wasm::serialize(module).unwrap()
+}
+
+fn arena() -> Arc<GameState> {
+    let gs = Arc::new(GameState::with_seed(7));
+    for i in 0..BOTS {
+        gs.combatant_entered(&format!("bot{}", i));
+    }
+    gs
+}
+
+// Times CYCLES cycles of the game loop while the bots started by `start`
+// make host calls as fast as they can
+fn run_match<S, T>(start: S) -> Duration
+where
+    S: Fn(&Arc<GameState>, String) -> T,
+    T: FnOnce(),
+{
+    let gs = arena();
+    let stops: Vec<_> = (0..BOTS).map(|i| start(&gs, format!("bot{}", i))).collect();
+    let mut game_loop = Gameloop::new(gs, CYCLES, BOTS, None);
+
+    let begin = Instant::now();
+    game_loop.start();
+    let elapsed = begin.elapsed();
+    stops.into_iter().for_each(|stop| stop());
+    elapsed
+}
+
+// Bots on the real runtime, reading the published snapshot
+fn start_runtime_bot(gs: &Arc<GameState>, name: String) -> impl FnOnce() {
+    let handle = Combatant::start(&name, scanning_bot(), gs.clone());
+    move || handle.stop()
+}
+
+// The same host calls reading the component hashes under their locks, the
+// way the runtime did before snapshots
+struct LockedHost {
+    game_state: Arc<GameState>,
+    name: String,
+    stop: Arc<AtomicBool>,
+}
+
+struct LockedResolver;
+
+impl ModuleImportResolver for LockedResolver {
+    fn resolve_func(
+        &self,
+        field: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, InterpreterError> {
+        let index = match field {
+            "scan" => SCAN_INDEX,
+            "loc_x" => LOCX_INDEX,
+            "speed" => SPEED_INDEX,
+            _ => return Err(InterpreterError::Function(field.to_string())),
+        };
+        Ok(FuncInstance::alloc_host(signature.clone(), index))
+    }
+}
+
+impl LockedHost {
+    fn dead(&self) -> bool {
+        readlock(&self.game_state.damage_components)
+            .get(&self.name)
+            .map_or(false, |dc| dc.dead())
+    }
+
+    fn scan(&self, degree: f32, resolution: f32) -> i32 {
+        writelock(&self.game_state.scanner_components)
+            .entry(self.name.clone())
+            .and_modify(|sc| sc.angle = degree as i32);
+
+        let mcs = readlock(&self.game_state.motion_components);
+        let dcs = readlock(&self.game_state.damage_components);
+        let players = self.game_state.players.read().unwrap();
+        let source = mcs[&self.name].position;
+        players
+            .iter()
+            .filter(|t| **t != self.name && dcs.get(*t).map_or(false, |dc| !dc.dead()))
+            .filter_map(|t| mcs.get(t))
+            .filter_map(|t| {
+                let heading = Rotation2::rotation_between(&Vector2::x(), &(t.position - source));
+                let range = nalgebra::distance(&source, &t.position);
+                if (heading.angle().to_degrees() - degree).abs() <= resolution.min(10.0)
+                    && range <= 700.0
+                {
+                    Some(range as i32)
+                } else {
+                    None
+                }
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+impl Externals for LockedHost {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        if self.stop.load(Ordering::SeqCst) {
+            return Err(Trap::new(wasmi::TrapKind::Unreachable));
+        }
+        let value = match index {
+            SCAN_INDEX if self.dead() => -1,
+            SCAN_INDEX => self.scan(args.nth::<i32>(0) as f32, args.nth::<i32>(1) as f32),
+            LOCX_INDEX => {
+                readlock(&self.game_state.motion_components)[&self.name]
+                    .position
+                    .x as i32
+            }
+            SPEED_INDEX if self.dead() => 0,
+            _ => readlock(&self.game_state.motion_components)[&self.name].speed,
+        };
+        Ok(Some(RuntimeValue::from(value)))
+    }
+}
+
+fn start_locked_bot(gs: &Arc<GameState>, name: String) -> impl FnOnce() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut host = LockedHost {
+        game_state: gs.clone(),
+        name,
+        stop: stop.clone(),
+    };
+    let thread = thread::spawn(move || {
+        let module = wasmi::Module::from_buffer(scanning_bot()).unwrap();
+        let imports = ImportsBuilder::new().with_resolver("env", &LockedResolver);
+        let instance = ModuleInstance::new(&module, &imports)
+            .unwrap()
+            .assert_no_start();
+        // the bot has been stopped once this returns
+        let _ = instance.invoke_export(botengine::BOTINIT_NAME, &[], &mut host);
+    });
+    move || {
+        stop.store(true, Ordering::SeqCst);
+        thread.join().unwrap();
+    }
+}
+
+fn host_calls(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("{} bots, {} cycles", BOTS, CYCLES));
+    group.sample_size(10);
+    group.bench_function("snapshot", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| run_match(start_runtime_bot)).sum())
+    });
+    group.bench_function("locks", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| run_match(start_locked_bot)).sum())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, host_calls);
+criterion_main!(benches);
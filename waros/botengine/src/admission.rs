@@ -0,0 +1,203 @@
+use crate::{Error, Kind, Result};
+
+/// Name of the custom section a bot author can embed their signature in.
+/// The signature covers the module with that section removed.
+pub const SIGNATURE_SECTION: &'static str = "waros.signature";
+
+const WASM_HEADER_LEN: usize = 8;
+const CUSTOM_SECTION_ID: u8 = 0;
+const SIGNATURE_LEN: usize = 64;
+
+fn refused(reason: String) -> Error {
+    Error {
+        kind: Kind::AdmissionRefused(reason),
+    }
+}
+
+// Splits a module into the bytes covered by its signature and the
+// signature embedded in it, if any
+pub fn split_signature(wasm: &[u8]) -> Result<(Vec<u8>, Option<Vec<u8>>)> {
+    if wasm.len() < WASM_HEADER_LEN {
+        return Err(refused("not a wasm module".to_string()));
+    }
+
+    let mut unsigned = wasm[..WASM_HEADER_LEN].to_vec();
+    let mut signature = None;
+    let mut pos = WASM_HEADER_LEN;
+    while pos < wasm.len() {
+        let start = pos;
+        let id = wasm[pos];
+        let (size, size_len) = read_leb128(&wasm[pos + 1..])?;
+        let payload = pos + 1 + size_len;
+        let end = payload
+            .checked_add(size as usize)
+            .filter(|e| *e <= wasm.len())
+            .ok_or_else(|| refused("truncated wasm section".to_string()))?;
+
+        if id == CUSTOM_SECTION_ID && custom_section_name(&wasm[payload..end])? == SIGNATURE_SECTION
+        {
+            if signature.is_some() {
+                return Err(refused("more than one signature section".to_string()));
+            }
+            let (name_len, name_len_size) = read_leb128(&wasm[payload..end])?;
+            signature = Some(wasm[payload + name_len_size + name_len as usize..end].to_vec());
+        } else {
+            unsigned.extend_from_slice(&wasm[start..end]);
+        }
+        pos = end;
+    }
+    Ok((unsigned, signature))
+}
+
+fn custom_section_name(payload: &[u8]) -> Result<&str> {
+    let (len, len_size) = read_leb128(payload)?;
+    payload
+        .get(len_size..len_size + len as usize)
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .ok_or_else(|| refused("malformed custom section name".to_string()))
+}
+
+// Reads an unsigned LEB128 value, returning it and the bytes it used
+fn read_leb128(bytes: &[u8]) -> Result<(u32, usize)> {
+    let mut value: u32 = 0;
+    for (i, b) in bytes.iter().take(5).enumerate() {
+        value |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(refused("malformed section size".to_string()))
+}
+
+// Detached signatures are either the raw 64 bytes or hex text
+pub fn decode_signature(bytes: &[u8]) -> Result<Vec<u8>> {
+    if bytes.len() == SIGNATURE_LEN {
+        return Ok(bytes.to_vec());
+    }
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| refused("signature is neither raw bytes nor hex".to_string()))?;
+    decode_hex(text.trim())
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return Err(refused(format!("odd length hex string {}", text)));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| refused(format!("invalid hex string {}", text)))
+        })
+        .collect()
+}
+
+/// Public keys whose signatures admit a bot into a match
+#[cfg(feature = "signed-bots")]
+#[derive(Debug, Default)]
+pub struct TrustedKeys {
+    keys: Vec<ed25519_dalek::PublicKey>,
+}
+
+#[cfg(feature = "signed-bots")]
+impl TrustedKeys {
+    pub fn new() -> TrustedKeys {
+        TrustedKeys { keys: Vec::new() }
+    }
+
+    // Reads hex encoded keys, one per line. Blank lines and lines starting
+    // with `#` are skipped.
+    pub fn from_file(path: &str) -> Result<TrustedKeys> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut trusted = TrustedKeys::new();
+        for line in contents.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                trusted.add(&decode_hex(line)?)?;
+            }
+        }
+        Ok(trusted)
+    }
+
+    pub fn add(&mut self, key: &[u8]) -> Result<()> {
+        let key = ed25519_dalek::PublicKey::from_bytes(key)
+            .map_err(|e| refused(format!("invalid public key: {}", e)))?;
+        self.keys.push(key);
+        Ok(())
+    }
+
+    // Checks a bot against the trusted keys. `detached` is the contents of
+    // a signature file; without one the module must carry a signature
+    // section.
+    pub fn admit(&self, wasm: &[u8], detached: Option<&[u8]>) -> Result<()> {
+        use std::convert::TryFrom;
+
+        let (unsigned, embedded) = split_signature(wasm)?;
+        let (message, signature) = match (detached, embedded) {
+            (Some(d), _) => (wasm.to_vec(), decode_signature(d)?),
+            (None, Some(s)) => (unsigned, s),
+            (None, None) => return Err(refused("bot is not signed".to_string())),
+        };
+        let signature = ed25519_dalek::Signature::try_from(&signature[..])
+            .map_err(|e| refused(format!("malformed signature: {}", e)))?;
+
+        if self
+            .keys
+            .iter()
+            .any(|k| k.verify_strict(&message, &signature).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(refused(
+                "signature does not match any trusted key".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    fn custom_section(name: &str, payload: &[u8]) -> Vec<u8> {
+        let mut body = vec![name.len() as u8];
+        body.extend_from_slice(name.as_bytes());
+        body.extend_from_slice(payload);
+        let mut section = vec![CUSTOM_SECTION_ID, body.len() as u8];
+        section.extend(body);
+        section
+    }
+
+    #[test]
+    fn signature_section_is_split_out() {
+        // an empty type section, the signature and an unrelated custom section
+        let types = [0x01, 0x01, 0x00];
+        let other = custom_section("name", &[0x00]);
+        let mut wasm = HEADER.to_vec();
+        wasm.extend_from_slice(&types);
+        wasm.extend(custom_section(SIGNATURE_SECTION, &[7; SIGNATURE_LEN]));
+        wasm.extend_from_slice(&other);
+
+        let (unsigned, signature) = split_signature(&wasm).unwrap();
+        let mut expected = HEADER.to_vec();
+        expected.extend_from_slice(&types);
+        expected.extend(other);
+        assert_eq!(expected, unsigned);
+        assert_eq!(Some(vec![7; SIGNATURE_LEN]), signature);
+
+        assert_eq!((HEADER.to_vec(), None), split_signature(&HEADER).unwrap());
+        assert!(split_signature(&wasm[..wasm.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn detached_signatures_decode() {
+        assert_eq!(
+            vec![1; SIGNATURE_LEN],
+            decode_signature(&[1; SIGNATURE_LEN]).unwrap()
+        );
+        assert_eq!(vec![0xab, 0x01], decode_signature(b"ab01\n").unwrap());
+        assert!(decode_signature(b"xyz").is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use crate::game::damage::DamageKind;
 use crate::game::motion::CollisionType;
 use nalgebra::Point2;
+use serde_derive::Serialize;
 use std::sync::mpsc::Sender;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum GameEvent {
     GameStarted,
     Collision {
@@ -31,6 +32,38 @@ pub enum GameEvent {
         cycle: u32,
         position: Point2<f32>,
     },
+    Scan {
+        cycle: u32,
+        player: String,
+        angle: i32,
+        resolution: i32,
+        range: i32,
+    },
+    BotOutput {
+        cycle: u32,
+        player: String,
+        text: String,
+    },
+    BotFault {
+        cycle: u32,
+        player: String,
+        reason: String,
+    },
+    FlagTaken {
+        cycle: u32,
+        player: String,
+    },
+    FlagDropped {
+        cycle: u32,
+        player: String,
+        position: Point2<f32>,
+    },
+    Score {
+        cycle: u32,
+        player: String,
+        points: u32,
+        total: u32,
+    },
     GameTerminated(i32),
 }
 
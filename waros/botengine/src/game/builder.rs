@@ -0,0 +1,214 @@
+use super::*;
+use crate::game::objectives::{GameMode, ObjectiveSystem};
+
+// Creates a system once the loop's logger is known
+type SystemFactory = Box<dyn FnOnce(Option<Sender<GameEvent>>) -> Box<dyn System>>;
+
+/// Assembles a `Gameloop` from an ordered list of named systems. Systems are
+/// applied in the order they end up in the list each cycle.
+pub struct GameloopBuilder {
+    game_state: Arc<GameState>,
+    systems: Vec<(String, SystemFactory)>,
+    max_cycles: u32,
+    num_combatants: usize,
+    logger: Option<Sender<GameEvent>>,
+}
+
+// Names of the built-in systems, for placing custom systems around them
+pub const SCANNER_SYSTEM: &str = "scanner";
+pub const MOTION_SYSTEM: &str = "motion";
+pub const PROJECTILE_SYSTEM: &str = "projectile";
+pub const DAMAGE_SYSTEM: &str = "damage";
+pub const OBJECTIVE_SYSTEM: &str = "objective";
+
+impl GameloopBuilder {
+    pub fn new(game_state: Arc<GameState>) -> GameloopBuilder {
+        GameloopBuilder {
+            game_state,
+            systems: Vec::new(),
+            max_cycles: u32::max_value(),
+            num_combatants: 0,
+            logger: None,
+        }
+    }
+
+    pub fn max_cycles(mut self, max_cycles: u32) -> GameloopBuilder {
+        self.max_cycles = max_cycles;
+        self
+    }
+
+    pub fn num_combatants(mut self, num_combatants: usize) -> GameloopBuilder {
+        self.num_combatants = num_combatants;
+        self
+    }
+
+    // Sets the logger handed to the systems and used for bot output. Systems
+    // are created by `build`, so this can come before or after them.
+    pub fn logger(mut self, logger: Option<Sender<GameEvent>>) -> GameloopBuilder {
+        self.logger = logger;
+        self
+    }
+
+    // Sets the game mode, which decides the objective and win condition
+    pub fn mode(self, mode: GameMode) -> GameloopBuilder {
+        self.game_state.set_mode(mode);
+        self
+    }
+
+    // Registers the built-in scanner, motion, projectile, damage and
+    // objective systems
+    pub fn with_default_systems(self) -> GameloopBuilder {
+        self.system_with_logger(SCANNER_SYSTEM, ScannerSystem::new)
+            .system_with_logger(MOTION_SYSTEM, MotionSystem::new)
+            .system_with_logger(PROJECTILE_SYSTEM, ProjectileSystem::new)
+            .system_with_logger(DAMAGE_SYSTEM, DamageSystem::new)
+            .system_with_logger(OBJECTIVE_SYSTEM, ObjectiveSystem::new)
+    }
+
+    // Appends a system to the end of the cycle
+    pub fn system<S: System + 'static>(self, name: &str, system: S) -> GameloopBuilder {
+        self.system_with_logger(name, move |_| system)
+    }
+
+    // Appends a system created from the loop's logger when it is built
+    pub fn system_with_logger<S, F>(mut self, name: &str, make: F) -> GameloopBuilder
+    where
+        S: System + 'static,
+        F: FnOnce(Option<Sender<GameEvent>>) -> S + 'static,
+    {
+        self.systems.push((name.to_string(), factory(make)));
+        self
+    }
+
+    // Inserts a system so that it runs just before the named system. Falls
+    // back to appending when no system has that name.
+    pub fn system_before<S: System + 'static>(
+        mut self,
+        existing: &str,
+        name: &str,
+        system: S,
+    ) -> GameloopBuilder {
+        let idx = self.position(existing).unwrap_or(self.systems.len());
+        self.systems.insert(idx, (name.to_string(), factory(move |_| system)));
+        self
+    }
+
+    // Inserts a system so that it runs just after the named system. Falls
+    // back to appending when no system has that name.
+    pub fn system_after<S: System + 'static>(
+        mut self,
+        existing: &str,
+        name: &str,
+        system: S,
+    ) -> GameloopBuilder {
+        let idx = self
+            .position(existing)
+            .map(|i| i + 1)
+            .unwrap_or(self.systems.len());
+        self.systems.insert(idx, (name.to_string(), factory(move |_| system)));
+        self
+    }
+
+    pub fn without_system(mut self, name: &str) -> GameloopBuilder {
+        self.systems.retain(|(n, _)| n != name);
+        self
+    }
+
+    // Registers a per-bot component type, created with `init` for every
+    // combatant that enters the game
+    pub fn component<T, F>(self, init: F) -> GameloopBuilder
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.game_state.register_component(init);
+        self
+    }
+
+    pub fn system_names(&self) -> Vec<&str> {
+        self.systems.iter().map(|(n, _)| n.as_str()).collect()
+    }
+
+    pub fn build(self) -> Gameloop {
+        let logger = self.logger;
+        Gameloop {
+            game_state: self.game_state,
+            systems: self
+                .systems
+                .into_iter()
+                .map(|(_, make)| make(logger.clone()))
+                .collect(),
+            cycle: 0,
+            max_cycles: self.max_cycles,
+            num_combatants: self.num_combatants,
+            logger,
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.systems.iter().position(|(n, _)| n == name)
+    }
+}
+
+fn factory<S, F>(make: F) -> SystemFactory
+where
+    S: System + 'static,
+    F: FnOnce(Option<Sender<GameEvent>>) -> S + 'static,
+{
+    Box::new(move |logger| Box::new(make(logger)) as Box<dyn System>)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Noop;
+
+    impl System for Noop {
+        fn apply(&self, _cycle: u32, _game_state: &Arc<GameState>) {}
+    }
+
+    #[test]
+    fn systems_can_be_ordered() {
+        let builder = GameloopBuilder::new(Arc::new(GameState::new()))
+            .with_default_systems()
+            .system_before(DAMAGE_SYSTEM, "first", Noop)
+            .system_after(SCANNER_SYSTEM, "second", Noop)
+            .without_system(PROJECTILE_SYSTEM)
+            .system("last", Noop);
+
+        assert_eq!(
+            vec!["scanner", "second", "motion", "first", "damage", "objective", "last"],
+            builder.system_names()
+        );
+    }
+
+    #[test]
+    fn logger_can_be_set_after_the_systems() {
+        use crate::game::commands::BotCommand;
+        use std::sync::mpsc::channel;
+
+        let gs = Arc::new(GameState::new());
+        gs.combatant_entered("me");
+        gs.commands.push(
+            "me",
+            BotCommand::Scan {
+                angle: 0,
+                resolution: 10,
+                range: 0,
+            },
+        );
+        gs.commands.apply_pending(&gs, &None);
+
+        let (sender, receiver) = channel();
+        let gameloop = GameloopBuilder::new(gs.clone())
+            .with_default_systems()
+            .logger(Some(sender))
+            .build();
+        gameloop.systems[0].apply(1, &gs);
+        match receiver.try_recv() {
+            Ok(GameEvent::Scan { .. }) => {}
+            other => panic!("scanner system has no logger: {:?}", other),
+        }
+    }
+}
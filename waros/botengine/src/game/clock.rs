@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+// Event flags a bot can wait on with `wait_until_event`. These are part of the
+// bot ABI and are mirrored in warsdk.
+pub const EVENT_DAMAGED: u32 = 1;
+pub const EVENT_COLLISION: u32 = 2;
+pub const EVENT_RELOADED: u32 = 4;
+pub const EVENT_STOPPED: u32 = 8;
+
+/// Tracks game loop cycle advancement so that bot threads can sleep on
+/// a condition variable instead of spinning on host calls.
+#[derive(Debug)]
+pub struct CycleClock {
+    state: Mutex<ClockState>,
+    advanced: Condvar,
+}
+
+#[derive(Debug)]
+struct ClockState {
+    cycle: u32,
+    finished: bool,
+    events: HashMap<String, u32>,
+    interrupted: HashSet<String>,
+}
+
+impl CycleClock {
+    pub fn new() -> CycleClock {
+        CycleClock {
+            state: Mutex::new(ClockState {
+                cycle: 0,
+                finished: false,
+                events: HashMap::new(),
+                interrupted: HashSet::new(),
+            }),
+            advanced: Condvar::new(),
+        }
+    }
+
+    pub fn cycle(&self) -> u32 {
+        self.lock().cycle
+    }
+
+    // Called by the game loop once all systems have been applied for a cycle
+    pub fn tick(&self) {
+        self.lock().cycle += 1;
+        self.advanced.notify_all();
+    }
+
+    // Called by the game loop when it terminates, releases all waiting bots
+    pub fn finish(&self) {
+        self.lock().finished = true;
+        self.advanced.notify_all();
+    }
+
+    pub fn finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    // Releases any wait the player is blocked in, and makes further waits
+    // return immediately until `resume` is called. Used when stopping a bot.
+    pub fn interrupt(&self, player: &str) {
+        self.lock().interrupted.insert(player.to_string());
+        self.advanced.notify_all();
+    }
+
+    pub fn resume(&self, player: &str) {
+        self.lock().interrupted.remove(player);
+    }
+
+    // Records that one or more events occurred for a player this cycle. Waiting
+    // bots are woken at the end of the cycle by `tick`.
+    pub fn raise(&self, player: &str, flags: u32) {
+        *self.lock().events.entry(player.to_string()).or_insert(0) |= flags;
+    }
+
+    // Blocks until the given number of cycles have elapsed. Returns the
+    // cycle at which the caller was woken.
+    pub fn wait_cycles(&self, player: &str, cycles: u32) -> u32 {
+        let mut state = self.lock();
+        let target = state.cycle.saturating_add(cycles);
+        while state.cycle < target && !state.finished && !state.interrupted.contains(player) {
+            state = self.advanced.wait(state).unwrap();
+        }
+        state.cycle
+    }
+
+    // Blocks until at least one of the events in `mask` is raised for the
+    // player. Only events raised after the call are considered. Returns the
+    // flags that fired, or 0 if the game ended first.
+    pub fn wait_for_event(&self, player: &str, mask: u32) -> u32 {
+        let mut state = self.lock();
+        state
+            .events
+            .entry(player.to_string())
+            .and_modify(|e| *e &= !mask);
+        loop {
+            let fired = state.events.get(player).map_or(0, |e| e & mask);
+            if fired != 0 {
+                state
+                    .events
+                    .entry(player.to_string())
+                    .and_modify(|e| *e &= !fired);
+                return fired;
+            }
+            if state.finished || mask == 0 || state.interrupted.contains(player) {
+                return 0;
+            }
+            state = self.advanced.wait(state).unwrap();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ClockState> {
+        self.state.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    // Runs `wait` on another thread, calling `step` on the clock until the
+    // waiter returns.
+    fn drive_until_woken<W, S>(clock: &Arc<CycleClock>, wait: W, step: S) -> u32
+    where
+        W: FnOnce(&CycleClock) -> u32 + Send + 'static,
+        S: Fn(&CycleClock),
+    {
+        let (sender, receiver) = channel();
+        let waiter = clock.clone();
+        thread::spawn(move || sender.send(wait(&waiter)).unwrap());
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(5)) {
+                Ok(v) => return v,
+                Err(_) => step(clock),
+            }
+        }
+    }
+
+    #[test]
+    fn wait_cycles_blocks_until_ticked() {
+        let clock = Arc::new(CycleClock::new());
+        let woken = drive_until_woken(&clock, |c| c.wait_cycles("bot", 3), |c| c.tick());
+        assert!(woken >= 3);
+    }
+
+    #[test]
+    fn wait_for_event_ignores_stale_events() {
+        let clock = Arc::new(CycleClock::new());
+        clock.raise("bot", EVENT_DAMAGED);
+
+        let fired = drive_until_woken(
+            &clock,
+            |c| c.wait_for_event("bot", EVENT_DAMAGED | EVENT_STOPPED),
+            |c| {
+                c.raise("bot", EVENT_STOPPED);
+                c.tick();
+            },
+        );
+        assert_eq!(EVENT_STOPPED, fired);
+    }
+
+    #[test]
+    fn interrupt_releases_only_that_player() {
+        let clock = Arc::new(CycleClock::new());
+        clock.interrupt("bot");
+        assert_eq!(0, clock.wait_cycles("bot", 1_000));
+        assert_eq!(0, clock.wait_for_event("bot", EVENT_DAMAGED));
+
+        clock.resume("bot");
+        let woken = drive_until_woken(&clock, |c| c.wait_cycles("bot", 2), |c| c.tick());
+        assert!(woken >= 2);
+    }
+
+    #[test]
+    fn finish_releases_waiters() {
+        let clock = Arc::new(CycleClock::new());
+        let fired = drive_until_woken(
+            &clock,
+            |c| c.wait_for_event("bot", EVENT_DAMAGED),
+            |c| c.finish(),
+        );
+        assert_eq!(0, fired);
+    }
+}
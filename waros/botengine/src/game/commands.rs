@@ -0,0 +1,225 @@
+use super::*;
+use crate::events::log_event;
+use crate::game::scanner::{ScanRecord, Sensor};
+use std::sync::Mutex;
+
+/// Where the game loop reports whether a queued `Cannon` command actually
+/// launched: 1 if it did, 0 if no projectile was loaded
+pub type LaunchReply = Arc<Mutex<Option<i32>>>;
+
+// Most commands one player can queue between two drains. Anything past
+// this is dropped, so a bot can't flood the queue.
+pub const MAX_COMMANDS_PER_CYCLE: usize = 64;
+
+/// Commands issued by bots through host calls. They are queued and applied
+/// by the game loop at the start of the next cycle.
+#[derive(Debug)]
+pub enum BotCommand {
+    Drive { heading: i32, speed: i32 },
+    Cannon { heading: i32, range: u32, reply: LaunchReply },
+    Scan { angle: i32, resolution: i32, range: i32 },
+    Sense { sensor: Sensor },
+    // text the bot wrote to stdout or stderr
+    Output { text: String },
+    // the bot trapped, failed to load or broke one of its limits
+    Fault { reason: String },
+}
+
+#[derive(Debug)]
+pub struct CommandQueue {
+    pending: Mutex<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    commands: Vec<(String, BotCommand)>,
+    // commands queued by each player since the last drain
+    counts: HashMap<String, usize>,
+}
+
+impl CommandQueue {
+    pub fn new() -> CommandQueue {
+        CommandQueue {
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    // Queues a command for the next cycle. Returns false, dropping the
+    // command, once the player has queued MAX_COMMANDS_PER_CYCLE since the
+    // last drain. Faults come from the engine and are always queued.
+    pub fn push(&self, player: &str, command: BotCommand) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        let capped = match command {
+            BotCommand::Fault { .. } => false,
+            _ => true,
+        };
+        if capped {
+            let count = pending.counts.entry(player.to_string()).or_insert(0);
+            if *count >= MAX_COMMANDS_PER_CYCLE {
+                return false;
+            }
+            *count += 1;
+        }
+        pending.commands.push((player.to_string(), command));
+        true
+    }
+
+    // Takes every command queued since the last drain, in submission order
+    pub fn drain(&self) -> Vec<(String, BotCommand)> {
+        let mut pending = self.pending.lock().unwrap();
+        pending.counts.clear();
+        std::mem::replace(&mut pending.commands, Vec::new())
+    }
+
+    pub fn apply_pending(&self, game_state: &GameState, logger: &Option<Sender<GameEvent>>) {
+        for (player, command) in self.drain() {
+            Self::apply(game_state, logger, &player, command);
+        }
+    }
+
+    fn apply(
+        game_state: &GameState,
+        logger: &Option<Sender<GameEvent>>,
+        player: &str,
+        command: BotCommand,
+    ) {
+        match command {
+            BotCommand::Drive { heading, speed } => {
+                writelock(&game_state.motion_components)
+                    .entry(player.to_string())
+                    .and_modify(|mc| {
+                        mc.origin = mc.position.clone();
+                        mc.distance_along_heading = 0;
+                        mc.heading = heading;
+                        mc.desired_speed = speed;
+                    });
+            }
+            BotCommand::Cannon {
+                heading,
+                range,
+                reply,
+            } => {
+                let mut launched = 0;
+                let mcs = readlock(&game_state.motion_components);
+                if let Some(mc) = mcs.get(player) {
+                    writelock(&game_state.projectile_components)
+                        .entry(player.to_string())
+                        .and_modify(|pc| {
+                            launched = pc.launch(&mc.position, heading, range);
+                        });
+                }
+                *reply.lock().unwrap() = Some(launched);
+            }
+            BotCommand::Scan {
+                angle,
+                resolution,
+                range,
+            } => {
+                writelock(&game_state.scanner_components)
+                    .entry(player.to_string())
+                    .and_modify(|sc| {
+                        sc.angle = angle;
+                        sc.pending_scans.push(ScanRecord {
+                            angle,
+                            resolution,
+                            range,
+                        });
+                    });
+            }
+            BotCommand::Sense { sensor } => {
+                let cycle = game_state.clock.cycle();
+                writelock(&game_state.scanner_components)
+                    .entry(player.to_string())
+                    .and_modify(|sc| sc.trigger(sensor, cycle));
+            }
+            BotCommand::Output { text } => log_event(
+                logger,
+                GameEvent::BotOutput {
+                    cycle: game_state.clock.cycle(),
+                    player: player.to_string(),
+                    text,
+                },
+            ),
+            BotCommand::Fault { reason } => log_event(
+                logger,
+                GameEvent::BotFault {
+                    cycle: game_state.clock.cycle(),
+                    player: player.to_string(),
+                    reason,
+                },
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drive_applied_on_drain() {
+        let gs = GameState::new();
+        gs.combatant_entered("bot");
+        gs.commands.push(
+            "bot",
+            BotCommand::Drive {
+                heading: 90,
+                speed: 50,
+            },
+        );
+        gs.publish_snapshot(0);
+        assert_eq!(0, gs.snapshot.load().bot("bot").unwrap().heading);
+
+        gs.commands.apply_pending(&gs, &None);
+        gs.publish_snapshot(1);
+        assert_eq!(90, gs.snapshot.load().bot("bot").unwrap().heading);
+        assert!(gs.commands.drain().is_empty());
+    }
+
+    #[test]
+    fn players_are_capped_per_cycle() {
+        let gs = GameState::new();
+        let drive = || BotCommand::Drive {
+            heading: 0,
+            speed: 10,
+        };
+        for _ in 0..MAX_COMMANDS_PER_CYCLE {
+            assert!(gs.commands.push("flood", drive()));
+        }
+        assert!(!gs.commands.push("flood", drive()));
+        assert!(gs.commands.push(
+            "flood",
+            BotCommand::Fault {
+                reason: "trapped".to_string()
+            }
+        ));
+        assert!(gs.commands.push("other", drive()));
+
+        assert_eq!(MAX_COMMANDS_PER_CYCLE + 2, gs.commands.drain().len());
+        assert!(gs.commands.push("flood", drive()));
+    }
+
+    #[test]
+    fn cannon_reports_the_real_launch() {
+        let gs = GameState::new();
+        gs.combatant_entered("bot");
+        let fire = || {
+            let reply = LaunchReply::default();
+            gs.commands.push(
+                "bot",
+                BotCommand::Cannon {
+                    heading: 0,
+                    range: 100,
+                    reply: reply.clone(),
+                },
+            );
+            reply
+        };
+
+        // only one projectile is loaded, so the second launch fails
+        let (first, second) = (fire(), fire());
+        gs.commands.apply_pending(&gs, &None);
+        assert_eq!(Some(1), *first.lock().unwrap());
+        assert_eq!(Some(0), *second.lock().unwrap());
+    }
+}
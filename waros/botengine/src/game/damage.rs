@@ -1,6 +1,9 @@
 use super::*;
 use crate::events::log_event;
+use crate::game::clock::{EVENT_COLLISION, EVENT_DAMAGED};
 use crate::game::{readlock, writelock};
+use nalgebra::Point2;
+use serde_derive::Serialize;
 
 pub struct DamageSystem {
     logger: Option<Sender<GameEvent>>,
@@ -58,6 +61,7 @@ impl DamageSystem {
       Some(mc) => match mc.collision {
         Some(CollisionType::Player(ref p)) => {
           dc.add_damage(DAMAGE_COLLISION);
+          game_state.clock.raise(player, EVENT_DAMAGED | EVENT_COLLISION);
           self.log_damage(
             cycle,
             DAMAGE_COLLISION,
@@ -68,6 +72,7 @@ impl DamageSystem {
         }
         Some(CollisionType::Wall(ref p)) => {
           dc.add_damage(DAMAGE_COLLISION);
+          game_state.clock.raise(player, EVENT_DAMAGED | EVENT_COLLISION);
           self.log_damage(
             cycle,
             DAMAGE_COLLISION,
@@ -88,19 +93,34 @@ impl DamageSystem {
     cycle: u32,
   ) {
     let pcs = game_state.projectile_components.read().unwrap();
-    let pc_opt = pcs.get(player);
-    match pc_opt {
-      Some(pc) => {
-        for x in 0..1 {
-          if pc.projectiles[x].active_hits.contains_key(player) {
-            let dmg: u32 = pc.projectiles[x].active_hits[player];
-            println!("Doing explosion damage {} to player {}", dmg, player);
-            dc.add_damage(dmg);
-            self.log_damage(cycle, dmg, DamageKind::Projectile, player);
-          }
+    // an explosion records hits on everyone in its blast radius, so look
+    // through every bot's projectiles, not only the player's own
+    for pc in pcs.values() {
+      for x in 0..1 {
+        let projectile = &pc.projectiles[x];
+        if projectile.active_hits.contains_key(player) {
+          let dmg: u32 = projectile.active_hits[player];
+          println!("Doing explosion damage {} to player {}", dmg, player);
+          dc.add_damage(dmg);
+          game_state.clock.raise(player, EVENT_DAMAGED);
+          self.record_hit_heading(player, game_state, &projectile.position);
+          self.log_damage(cycle, dmg, DamageKind::Projectile, player);
         }
       }
-      None => {}
+    }
+  }
+  // Remembers where the blast came from for the last_hit_heading sensor
+  fn record_hit_heading(
+    &self,
+    player: &str,
+    game_state: &Arc<GameState>,
+    blast: &Point2<f32>,
+  ) {
+    if let Some(mc) = readlock(&game_state.motion_components).get(player) {
+      let heading = ScannerSystem::heading_to_target(&mc.position, blast);
+      writelock(&game_state.scanner_components)
+        .entry(player.to_string())
+        .and_modify(|sc| sc.last_hit_heading = Some(heading as i32));
     }
   }
   fn log_damage(&self, cycle: u32, amount: u32, kind: DamageKind,
@@ -128,7 +148,7 @@ pub struct DamageComponent {
   pub status: DamageStatus,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum DamageKind {
   Collision(CollisionType),
   Projectile,
@@ -156,3 +176,33 @@ impl DamageComponent {
 
 const DAMAGE_COLLISION: u32 = 2;
 pub const DAMAGE_MAX: u32 = 100;
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn explosions_damage_other_bots() {
+    let gs = Arc::new(GameState::new());
+    for (name, x) in [("shooter", 100.0_f32), ("target", 500.0)].iter() {
+      gs.combatant_entered(name);
+      writelock(&gs.motion_components)
+        .entry(name.to_string())
+        .and_modify(|mc| mc.position = Point2::new(*x, 100.0));
+    }
+    writelock(&gs.projectile_components)
+      .entry("shooter".to_string())
+      .and_modify(|pc| {
+        pc.projectiles[0].position = Point2::new(480.0, 100.0);
+        pc.projectiles[0].active_hits.insert("target".to_string(), 10);
+      });
+
+    DamageSystem::new(None).apply(1, &gs);
+
+    let dcs = readlock(&gs.damage_components);
+    assert_eq!(10, dcs["target"].damage);
+    assert_eq!(0, dcs["shooter"].damage);
+    let scs = readlock(&gs.scanner_components);
+    assert_eq!(Some(-180), scs["target"].last_hit_heading);
+  }
+}
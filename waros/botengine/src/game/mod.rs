@@ -1,23 +1,37 @@
+pub use self::builder::{
+    GameloopBuilder, DAMAGE_SYSTEM, MOTION_SYSTEM, OBJECTIVE_SYSTEM, PROJECTILE_SYSTEM,
+    SCANNER_SYSTEM,
+};
+use self::clock::CycleClock;
+use self::commands::CommandQueue;
 use self::damage::*;
 use self::motion::*;
+use self::objectives::{GameMode, ObjectiveState};
 use self::projectiles::*;
+use self::registry::ComponentRegistry;
 use self::scanner::*;
+use self::snapshot::GameSnapshot;
 use crate::events::GameEvent;
+use arc_swap::ArcSwap;
+use rand::{rngs::StdRng, SeedableRng};
 use std::collections::HashMap;
 use std::sync::{mpsc::Sender, Arc, RwLock};
 use std::sync::{RwLockReadGuard, RwLockWriteGuard};
 
 pub struct Gameloop {
     game_state: Arc<GameState>,
-    systems: Vec<Box<System>>,
+    systems: Vec<Box<dyn System>>,
     cycle: u32,
     max_cycles: u32,
     num_combatants: usize,
+    logger: Option<Sender<GameEvent>>,
 }
 
 #[derive(Debug)]
 pub enum LoopTerminationReason {
     CycleCountExceeded,
+    LastBotStanding(Option<String>),
+    ObjectiveComplete(String),
 }
 
 pub trait System {
@@ -31,39 +45,78 @@ impl Gameloop {
         num_combatants: usize,
         logger: Option<Sender<GameEvent>>,
     ) -> Gameloop {
-        Gameloop {
-            game_state,
-            systems: vec![
-                Box::new(ScannerSystem::new(logger.clone())),
-                Box::new(MotionSystem::new(logger.clone())),
-                Box::new(ProjectileSystem::new(logger.clone())),
-                Box::new(DamageSystem::new(logger.clone())),
-            ],
-            cycle: 0,
-            max_cycles,
-            num_combatants,
-        }
+        Gameloop::builder(game_state)
+            .max_cycles(max_cycles)
+            .num_combatants(num_combatants)
+            .logger(logger)
+            .with_default_systems()
+            .build()
+    }
+
+    pub fn builder(game_state: Arc<GameState>) -> GameloopBuilder {
+        GameloopBuilder::new(game_state)
     }
 
     pub fn start(&mut self) -> LoopTerminationReason {
         loop {
+            self.game_state
+                .commands
+                .apply_pending(&self.game_state, &self.logger);
             self.systems
                 .iter()
                 .for_each(|s| s.apply(self.cycle, &self.game_state));
 
             self.cycle = self.cycle + 1;
+            self.game_state.publish_snapshot(self.cycle);
+            self.game_state.clock.tick();
 
-            if self.cycle >= self.max_cycles {
-                return LoopTerminationReason::CycleCountExceeded;
+            if let Some(reason) = self.check_termination() {
+                self.game_state.clock.finish();
+                return reason;
             }
         }
     }
+
+    fn check_termination(&self) -> Option<LoopTerminationReason> {
+        if self.cycle >= self.max_cycles {
+            return Some(LoopTerminationReason::CycleCountExceeded);
+        }
+
+        let os = self.game_state.objectives.read().unwrap();
+        match os.mode {
+            GameMode::LastBotStanding => {
+                // wait for everyone to enter before declaring a survivor
+                let players = self.game_state.players.read().unwrap();
+                if self.num_combatants < 2 || players.len() < self.num_combatants {
+                    return None;
+                }
+                let dcs = readlock(&self.game_state.damage_components);
+                let living: Vec<_> = players
+                    .iter()
+                    .filter(|p| dcs.get(*p).map_or(false, |dc| !dc.dead()))
+                    .collect();
+                if living.len() <= 1 {
+                    Some(LoopTerminationReason::LastBotStanding(
+                        living.first().map(|p| p.to_string()),
+                    ))
+                } else {
+                    None
+                }
+            }
+            GameMode::CycleLimit => None,
+            _ => os.winner().map(LoopTerminationReason::ObjectiveComplete),
+        }
+    }
 }
 
 
 pub type ReadWriteLocked<T> = Arc<RwLock<T>>;
 pub type ComponentHash<T> = ReadWriteLocked<HashMap<String, T>>;
 
+/// The component hashes are the back buffer, written only by the game loop.
+/// Bots read the published `snapshot` and submit changes through `commands`.
+/// The built-in component hashes are registered in `registry` like any
+/// other, and kept here as fields for quick access.
 #[derive(Debug)]
 pub struct GameState {
     pub players: ReadWriteLocked<Vec<String>>,
@@ -71,44 +124,113 @@ pub struct GameState {
     pub damage_components: ComponentHash<DamageComponent>,
     pub scanner_components: ComponentHash<ScannerComponent>,
     pub projectile_components: ComponentHash<ProjectileComponent>,
+    pub clock: CycleClock,
+    pub snapshot: ArcSwap<GameSnapshot>,
+    pub commands: CommandQueue,
+    pub registry: ComponentRegistry,
+    pub objectives: RwLock<ObjectiveState>,
+    pub seed: u64,
 }
 
 impl GameState {
     pub fn new() -> GameState {
+        Self::with_seed(rand::random())
+    }
+
+    // Game state whose spawn positions and bot `rand` calls are derived
+    // from `seed`, so a match can be replayed
+    pub fn with_seed(seed: u64) -> GameState {
+        let registry = ComponentRegistry::new();
         GameState {
             players: Arc::new(RwLock::new(Vec::new())),
-            motion_components: Arc::new(RwLock::new(HashMap::new())),
-            damage_components: Arc::new(RwLock::new(HashMap::new())),
-            scanner_components: Arc::new(RwLock::new(HashMap::new())),
-            projectile_components: Arc::new(RwLock::new(HashMap::new())),
+            motion_components: registry.register_per_player(move |player| {
+                MotionComponent::with_rng(&mut spawn_rng(seed, player))
+            }),
+            damage_components: registry.register(DamageComponent::new),
+            scanner_components: registry.register(ScannerComponent::new),
+            projectile_components: registry.register(ProjectileComponent::new),
+            clock: CycleClock::new(),
+            snapshot: ArcSwap::from_pointee(GameSnapshot::empty()),
+            commands: CommandQueue::new(),
+            registry,
+            objectives: RwLock::new(ObjectiveState::new(GameMode::CycleLimit)),
+            seed,
         }
     }
 
+    // Random number generator for a bot's `rand` host calls
+    pub fn bot_rng(&self, module_name: &str) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ name_hash(module_name))
+    }
+
+    pub fn set_mode(&self, mode: GameMode) {
+        *self.objectives.write().unwrap() = ObjectiveState::new(mode);
+    }
+
+    // Adds a per-bot component type alongside the built-in ones
+    pub fn register_component<T, F>(&self, init: F) -> ComponentHash<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.registry.register(init)
+    }
+
+    pub fn components<T: Send + Sync + 'static>(&self) -> Option<ComponentHash<T>> {
+        self.registry.get::<T>()
+    }
+
+    pub fn publish_snapshot(&self, cycle: u32) {
+        self.snapshot
+            .store(Arc::new(GameSnapshot::capture(self, cycle)));
+    }
+
     pub fn combatant_entered(&self, module_name: &str) {
-        self.players.write().unwrap().push(module_name.to_string());
-        self.motion_components
-            .write()
-            .unwrap()
-            .entry(module_name.to_string())
-            .or_insert(MotionComponent::new());
-        self.damage_components
-            .write()
-            .unwrap()
-            .entry(module_name.to_string())
-            .or_insert(DamageComponent::new());
-        self.scanner_components
-            .write()
-            .unwrap()
-            .entry(module_name.to_string())
-            .or_insert(ScannerComponent::new());
-        self.projectile_components
-            .write()
-            .unwrap()
+        {
+            // a bot re-entering after a swap keeps its original slot
+            let mut players = self.players.write().unwrap();
+            if !players.iter().any(|p| p == module_name) {
+                players.push(module_name.to_string());
+            }
+        }
+        self.registry.combatant_entered(module_name);
+    }
+
+    // Enters a player whose bot could not be loaded, already dead, so it
+    // still counts towards the combatants a LastBotStanding match waits for
+    pub fn combatant_failed(&self, module_name: &str) {
+        self.combatant_entered(module_name);
+        writelock(&self.damage_components)
             .entry(module_name.to_string())
-            .or_insert(ProjectileComponent::new());
+            .and_modify(|dc| {
+                dc.damage = DAMAGE_MAX;
+                dc.status = DamageStatus::Dead;
+            });
+    }
+
+    // Replaces all of a player's components and objective progress with
+    // fresh ones, as if it had just entered the game
+    pub fn reset_combatant(&self, module_name: &str) {
+        self.registry.remove(module_name);
+        self.objectives.write().unwrap().remove_player(module_name);
+        self.combatant_entered(module_name);
     }
 }
 
+// FNV-1a, so the streams derived from a name are stable between builds
+fn name_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Where a player spawns depends only on the seed and its name, not on the
+// order bot threads happen to enter in. Kept apart from the bot's own
+// `rand` stream.
+fn spawn_rng(seed: u64, player: &str) -> StdRng {
+    StdRng::seed_from_u64(seed.rotate_left(32) ^ name_hash(player))
+}
+
 pub fn readlock<'a, T>(
     component: &'a ComponentHash<T>
 ) -> RwLockReadGuard<'a, HashMap<String, T>> {
@@ -124,7 +246,42 @@ pub fn writelock<'a, T>(
 const MAX_X: f32 = 1000.0;
 const MAX_Y: f32 = 1000.0;
 
+pub mod builder;
+pub mod clock;
+pub mod commands;
 pub mod damage;
 pub mod motion;
+pub mod objectives;
 mod projectiles;
+pub mod registry;
 pub mod scanner;
+pub mod snapshot;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spawns_do_not_depend_on_entry_order() {
+        let positions = |order: &[&str]| {
+            let gs = GameState::with_seed(42);
+            order.iter().for_each(|p| gs.combatant_entered(p));
+            let mcs = readlock(&gs.motion_components);
+            (mcs["a"].position, mcs["b"].position)
+        };
+        assert_eq!(positions(&["a", "b"]), positions(&["b", "a"]));
+    }
+
+    #[test]
+    fn failed_bots_count_as_entered() {
+        let gs = Arc::new(GameState::new());
+        gs.set_mode(GameMode::LastBotStanding);
+        gs.combatant_entered("survivor");
+        gs.combatant_failed("broken");
+
+        match Gameloop::new(gs, 1000, 2, None).start() {
+            LoopTerminationReason::LastBotStanding(Some(ref p)) if p == "survivor" => {}
+            other => panic!("unexpected termination {:?}", other),
+        }
+    }
+}
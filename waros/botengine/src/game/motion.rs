@@ -1,5 +1,7 @@
 use super::*;
+use crate::game::clock::EVENT_STOPPED;
 use nalgebra::{Point2, Rotation2, Vector2};
+use serde_derive::Serialize;
 
 #[derive(Debug)]
 pub struct MotionComponent {
@@ -12,7 +14,7 @@ pub struct MotionComponent {
     pub collision: Option<CollisionType>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum CollisionType {
     Wall(Point2<f32>),
     Player(String),
@@ -20,8 +22,11 @@ pub enum CollisionType {
 
 impl MotionComponent {
     pub fn new() -> MotionComponent {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
+        Self::with_rng(&mut rand::thread_rng())
+    }
+
+    // Places the bot at a random starting position drawn from `rng`
+    pub fn with_rng<R: rand::Rng>(rng: &mut R) -> MotionComponent {
         // starting at 0 sometimes starts at -0.000003059797
         let x: f32 = rng.gen_range(1.0, MAX_X - 1.0);
         let y: f32 = rng.gen_range(1.0, MAX_Y - 1.0);
@@ -132,7 +137,13 @@ impl System for MotionSystem {
                 .write()
                 .unwrap()
                 .entry(p.to_string())
-                .and_modify(|mc| Self::advance(mc));
+                .and_modify(|mc| {
+                    let was_moving = mc.speed > 0;
+                    Self::advance(mc);
+                    if was_moving && mc.speed == 0 {
+                        game_state.clock.raise(p, EVENT_STOPPED);
+                    }
+                });
         });
     }
 }
@@ -0,0 +1,345 @@
+use super::*;
+use crate::events::log_event;
+use nalgebra::Point2;
+
+#[derive(Debug, Clone)]
+pub enum GameMode {
+    // No objective: the match runs until its cycle limit
+    CycleLimit,
+    // The match ends once at most one bot is left alive
+    LastBotStanding,
+    // A single flag spawns at `flag`. Driving over it picks it up, driving
+    // back to the spot the bot entered the arena scores a capture.
+    CaptureTheFlag {
+        flag: Point2<f32>,
+        captures_to_win: u32,
+    },
+    // A bot alone inside the zone scores a point every cycle
+    KingOfTheHill {
+        zone: Point2<f32>,
+        radius: f32,
+        score_to_win: u32,
+    },
+}
+
+impl GameMode {
+    pub fn capture_the_flag(x: f32, y: f32, captures_to_win: u32) -> GameMode {
+        GameMode::CaptureTheFlag {
+            flag: Point2::new(x, y),
+            captures_to_win,
+        }
+    }
+
+    pub fn king_of_the_hill(x: f32, y: f32, radius: f32, score_to_win: u32) -> GameMode {
+        GameMode::KingOfTheHill {
+            zone: Point2::new(x, y),
+            radius,
+            score_to_win,
+        }
+    }
+}
+
+// Values returned to bots by the `objective_state` host call
+pub const OBJECTIVE_NONE: i32 = 0;
+pub const OBJECTIVE_FREE: i32 = 1;
+pub const OBJECTIVE_HELD_BY_ME: i32 = 2;
+pub const OBJECTIVE_HELD_BY_OTHER: i32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct ObjectiveState {
+    pub mode: GameMode,
+    pub flag_position: Option<Point2<f32>>,
+    pub holder: Option<String>,
+    pub scores: HashMap<String, u32>,
+    pub home_bases: HashMap<String, Point2<f32>>,
+}
+
+impl ObjectiveState {
+    pub fn new(mode: GameMode) -> ObjectiveState {
+        let flag_position = match mode {
+            GameMode::CaptureTheFlag { flag, .. } => Some(flag),
+            _ => None,
+        };
+        ObjectiveState {
+            mode,
+            flag_position,
+            holder: None,
+            scores: HashMap::new(),
+            home_bases: HashMap::new(),
+        }
+    }
+
+    pub fn score(&self, player: &str) -> u32 {
+        self.scores.get(player).cloned().unwrap_or(0)
+    }
+
+    // Forgets the player's score and home base. A flag it was carrying is
+    // dropped where the player last was.
+    pub fn remove_player(&mut self, player: &str) {
+        self.scores.remove(player);
+        self.home_bases.remove(player);
+        if self.holder.as_ref().map_or(false, |h| h == player) {
+            self.holder = None;
+        }
+    }
+
+    // Where the given player should be heading next
+    pub fn target_for(&self, player: &str) -> Option<Point2<f32>> {
+        match self.mode {
+            GameMode::CycleLimit | GameMode::LastBotStanding => None,
+            GameMode::CaptureTheFlag { .. } => {
+                if self.holder.as_ref().map_or(false, |h| h == player) {
+                    self.home_bases.get(player).cloned()
+                } else {
+                    self.flag_position
+                }
+            }
+            GameMode::KingOfTheHill { zone, .. } => Some(zone),
+        }
+    }
+
+    pub fn state_for(&self, player: &str) -> i32 {
+        match (&self.mode, &self.holder) {
+            (GameMode::CycleLimit, _) | (GameMode::LastBotStanding, _) => OBJECTIVE_NONE,
+            (_, None) => OBJECTIVE_FREE,
+            (_, Some(h)) if h == player => OBJECTIVE_HELD_BY_ME,
+            (_, Some(_)) => OBJECTIVE_HELD_BY_OTHER,
+        }
+    }
+
+    // The player that has met the mode's win condition, if any. Should two
+    // get there together, the higher score wins, then the first by name.
+    pub fn winner(&self) -> Option<String> {
+        let target = match self.mode {
+            GameMode::CycleLimit | GameMode::LastBotStanding => return None,
+            GameMode::CaptureTheFlag {
+                captures_to_win, ..
+            } => captures_to_win,
+            GameMode::KingOfTheHill { score_to_win, .. } => score_to_win,
+        };
+        self.scores
+            .iter()
+            .filter(|(_, s)| **s >= target)
+            .max_by(|(p1, s1), (p2, s2)| s1.cmp(s2).then_with(|| p2.cmp(p1)))
+            .map(|(p, _)| p.to_string())
+    }
+}
+
+pub struct ObjectiveSystem {
+    logger: Option<Sender<GameEvent>>,
+}
+
+impl ObjectiveSystem {
+    pub fn new(logger: Option<Sender<GameEvent>>) -> ObjectiveSystem {
+        ObjectiveSystem { logger }
+    }
+
+    fn advance_flag(
+        &self,
+        os: &mut ObjectiveState,
+        positions: &[(String, Point2<f32>, bool)],
+        spawn: Point2<f32>,
+        cycle: u32,
+    ) {
+        if let Some(carrier) = os.holder.clone() {
+            match positions.iter().find(|(p, _, _)| *p == carrier) {
+                Some((_, pos, true)) => {
+                    os.holder = None;
+                    os.flag_position = Some(pos.clone());
+                    log_event(
+                        &self.logger,
+                        GameEvent::FlagDropped {
+                            cycle,
+                            player: carrier,
+                            position: pos.clone(),
+                        },
+                    );
+                }
+                Some((_, pos, false)) => {
+                    os.flag_position = Some(pos.clone());
+                    let home = os.home_bases.get(&carrier).cloned();
+                    if let Some(home) = home {
+                        if ScannerSystem::range_to_target(pos, &home) <= OBJECTIVE_RADIUS {
+                            os.holder = None;
+                            os.flag_position = Some(spawn);
+                            self.add_score(os, &carrier, 1, cycle);
+                        }
+                    }
+                }
+                None => {}
+            }
+        } else if let Some(flag) = os.flag_position {
+            let taker = positions.iter().find(|(_, pos, dead)| {
+                !*dead && ScannerSystem::range_to_target(pos, &flag) <= OBJECTIVE_RADIUS
+            });
+            if let Some((p, _, _)) = taker {
+                os.holder = Some(p.to_string());
+                log_event(
+                    &self.logger,
+                    GameEvent::FlagTaken {
+                        cycle,
+                        player: p.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn advance_hill(
+        &self,
+        os: &mut ObjectiveState,
+        positions: &[(String, Point2<f32>, bool)],
+        zone: Point2<f32>,
+        radius: f32,
+        cycle: u32,
+    ) {
+        let inside: Vec<_> = positions
+            .iter()
+            .filter(|(_, pos, dead)| !*dead && ScannerSystem::range_to_target(pos, &zone) <= radius)
+            .collect();
+
+        // a contested hill scores for nobody
+        os.holder = if inside.len() == 1 {
+            Some(inside[0].0.to_string())
+        } else {
+            None
+        };
+        if let Some(holder) = os.holder.clone() {
+            self.add_score(os, &holder, 1, cycle);
+        }
+    }
+
+    fn add_score(&self, os: &mut ObjectiveState, player: &str, points: u32, cycle: u32) {
+        let total = {
+            let score = os.scores.entry(player.to_string()).or_insert(0);
+            *score += points;
+            *score
+        };
+        log_event(
+            &self.logger,
+            GameEvent::Score {
+                cycle,
+                player: player.to_string(),
+                points,
+                total,
+            },
+        );
+    }
+}
+
+impl System for ObjectiveSystem {
+    fn apply(&self, cycle: u32, game_state: &Arc<GameState>) {
+        let mut os = game_state.objectives.write().unwrap();
+        match os.mode {
+            GameMode::CycleLimit | GameMode::LastBotStanding => return,
+            _ => {}
+        }
+
+        let positions: Vec<(String, Point2<f32>, bool)> = {
+            let mcs = readlock(&game_state.motion_components);
+            let dcs = readlock(&game_state.damage_components);
+            game_state
+                .players
+                .read()
+                .unwrap()
+                .iter()
+                .filter_map(|p| match (mcs.get(p), dcs.get(p)) {
+                    (Some(mc), Some(dc)) => Some((p.to_string(), mc.position.clone(), dc.dead())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // bots score captures at the spot they entered the arena
+        for (p, pos, _) in positions.iter() {
+            os.home_bases.entry(p.to_string()).or_insert(pos.clone());
+        }
+
+        match os.mode.clone() {
+            GameMode::CaptureTheFlag { flag, .. } => {
+                self.advance_flag(&mut os, &positions, flag, cycle)
+            }
+            GameMode::KingOfTheHill { zone, radius, .. } => {
+                self.advance_hill(&mut os, &positions, zone, radius, cycle)
+            }
+            GameMode::CycleLimit | GameMode::LastBotStanding => {}
+        }
+    }
+}
+
+const OBJECTIVE_RADIUS: f32 = 20.0;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bot(name: &str, x: f32, y: f32) -> (String, Point2<f32>, bool) {
+        (name.to_string(), Point2::new(x, y), false)
+    }
+
+    #[test]
+    fn flag_is_taken_and_captured() {
+        let spawn = Point2::new(500.0_f32, 500.0);
+        let system = ObjectiveSystem::new(None);
+        let mut os = ObjectiveState::new(GameMode::CaptureTheFlag {
+            flag: spawn,
+            captures_to_win: 1,
+        });
+        os.home_bases
+            .insert("a".to_string(), Point2::new(100.0, 100.0));
+
+        system.advance_flag(&mut os, &[bot("a", 505.0, 495.0)], spawn, 1);
+        assert_eq!(OBJECTIVE_HELD_BY_ME, os.state_for("a"));
+        assert_eq!(Some(Point2::new(100.0, 100.0)), os.target_for("a"));
+
+        system.advance_flag(&mut os, &[bot("a", 110.0, 100.0)], spawn, 2);
+        assert_eq!(OBJECTIVE_FREE, os.state_for("a"));
+        assert_eq!(Some(spawn), os.flag_position);
+        assert_eq!(Some("a".to_string()), os.winner());
+    }
+
+    #[test]
+    fn contested_hill_scores_nobody() {
+        let zone = Point2::new(500.0_f32, 500.0);
+        let system = ObjectiveSystem::new(None);
+        let mut os = ObjectiveState::new(GameMode::KingOfTheHill {
+            zone,
+            radius: 50.0,
+            score_to_win: 10,
+        });
+
+        system.advance_hill(
+            &mut os,
+            &[bot("a", 500.0, 510.0), bot("b", 900.0, 900.0)],
+            zone,
+            50.0,
+            1,
+        );
+        system.advance_hill(
+            &mut os,
+            &[bot("a", 500.0, 510.0), bot("b", 490.0, 500.0)],
+            zone,
+            50.0,
+            2,
+        );
+        assert_eq!(1, os.score("a"));
+        assert_eq!(0, os.score("b"));
+        assert_eq!(OBJECTIVE_FREE, os.state_for("a"));
+    }
+
+    #[test]
+    fn winner_is_deterministic() {
+        let mut os = ObjectiveState::new(GameMode::KingOfTheHill {
+            zone: Point2::new(0.0, 0.0),
+            radius: 50.0,
+            score_to_win: 10,
+        });
+        for (p, s) in [("d", 9), ("c", 10), ("b", 11), ("a", 10), ("e", 11)].iter() {
+            os.scores.insert(p.to_string(), *s);
+        }
+        assert_eq!(Some("b".to_string()), os.winner());
+
+        os.mode = GameMode::CycleLimit;
+        assert_eq!(None, os.winner());
+    }
+}
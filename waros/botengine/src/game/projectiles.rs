@@ -1,5 +1,6 @@
 use super::*;
 use crate::events::{log_event, GameEvent};
+use crate::game::clock::EVENT_RELOADED;
 use nalgebra::Point2;
 
 #[derive(Debug, PartialEq)]
@@ -29,16 +30,26 @@ impl ProjectileComponent {
     // Attempt to fire. Returns 0 if there's no available missiles, 1 if successful.
     pub fn launch(&mut self, origin: &Point2<f32>, angle: i32, range: u32) -> i32 {
         let range = range.min(PROJECTILE_MAX_RANGE);
-        for idx in 0..1 {
-            // can only launch if reload period (cycle count) has lapsed
-            if self.projectiles[idx].status == ProjectileStatus::Available
-                && self.projectiles[idx].cycle_count == 0
-            {
+        match self.available_slot() {
+            Some(idx) => {
                 self.projectiles[idx].launch(origin, angle, range);
-                return 1;
+                1
             }
+            None => 0,
         }
-        return 0;
+    }
+
+    // Whether a call to launch would currently succeed
+    pub fn ready(&self) -> bool {
+        self.available_slot().is_some()
+    }
+
+    fn available_slot(&self) -> Option<usize> {
+        // can only launch if reload period (cycle count) has lapsed
+        (0..1).find(|&idx| {
+            self.projectiles[idx].status == ProjectileStatus::Available
+                && self.projectiles[idx].cycle_count == 0
+        })
     }
 }
 
@@ -112,7 +123,7 @@ impl ProjectileSystem {
         self.move_projectile(projectile, cycle);
         self.check_wall_collisions(projectile, cycle);
         self.inflict_splash_damage(projectile, gs);
-        self.decay_projectile(projectile);
+        self.decay_projectile(projectile, gs, player);
     }
 
     fn launch_projectile(&self, projectile: &mut Projectile, cycle: u32, player: &str) {
@@ -144,9 +155,15 @@ impl ProjectileSystem {
         }
     }
 
-    fn decay_projectile(&self, projectile: &mut Projectile) {
+    fn decay_projectile(&self, projectile: &mut Projectile, gs: &Arc<GameState>, player: &str) {
         projectile.cycle_count = match projectile.cycle_count.checked_sub(1) {
-            Some(n) => n,
+            Some(n) => {
+                // reload period has just lapsed
+                if n == 0 && projectile.status == ProjectileStatus::Available {
+                    gs.clock.raise(player, EVENT_RELOADED);
+                }
+                n
+            }
             None => 0,
         };
 
@@ -0,0 +1,131 @@
+use super::*;
+use std::any::{Any, TypeId};
+use std::fmt;
+
+type ComponentInit = Box<dyn Fn(&str) + Send + Sync>;
+type ComponentRemove = Box<dyn Fn(&str) + Send + Sync>;
+
+/// Holds the component hash of every per-bot component type, the built-in
+/// motion, damage, scanner and projectile components included.
+pub struct ComponentRegistry {
+    hashes: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+    initializers: RwLock<Vec<ComponentInit>>,
+    removers: RwLock<Vec<ComponentRemove>>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> ComponentRegistry {
+        ComponentRegistry {
+            hashes: RwLock::new(HashMap::new()),
+            initializers: RwLock::new(Vec::new()),
+            removers: RwLock::new(Vec::new()),
+        }
+    }
+
+    // Registers a component type. Every combatant that enters the game gets
+    // a component created by `init`. Registering the same type twice keeps
+    // the first registration.
+    pub fn register<T, F>(&self, init: F) -> ComponentHash<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.register_per_player(move |_| init())
+    }
+
+    // Like `register`, with `init` given the name of the entering player
+    pub fn register_per_player<T, F>(&self, init: F) -> ComponentHash<T>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&str) -> T + Send + Sync + 'static,
+    {
+        if let Some(existing) = self.get::<T>() {
+            return existing;
+        }
+
+        let hash: ComponentHash<T> = Arc::new(RwLock::new(HashMap::new()));
+        self.hashes
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Box::new(hash.clone()));
+
+        let target = hash.clone();
+        self.initializers
+            .write()
+            .unwrap()
+            .push(Box::new(move |player: &str| {
+                writelock(&target)
+                    .entry(player.to_string())
+                    .or_insert_with(|| init(player));
+            }));
+
+        let target = hash.clone();
+        self.removers
+            .write()
+            .unwrap()
+            .push(Box::new(move |player: &str| {
+                writelock(&target).remove(player);
+            }));
+        hash
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<ComponentHash<T>> {
+        self.hashes
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .and_then(|h| h.downcast_ref::<ComponentHash<T>>())
+            .cloned()
+    }
+
+    pub fn combatant_entered(&self, player: &str) {
+        self.initializers
+            .read()
+            .unwrap()
+            .iter()
+            .for_each(|init| init(player));
+    }
+
+    pub fn remove(&self, player: &str) {
+        self.removers
+            .read()
+            .unwrap()
+            .iter()
+            .for_each(|remove| remove(player));
+    }
+}
+
+impl fmt::Debug for ComponentRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ComponentRegistry {{ {} registered }}",
+            self.hashes.read().unwrap().len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Flag(u32);
+
+    #[test]
+    fn registered_components_follow_combatants() {
+        let gs = GameState::new();
+        gs.register_component(|| Flag(7));
+        gs.combatant_entered("bot");
+
+        let flags = gs.components::<Flag>().unwrap();
+        assert_eq!(Some(&Flag(7)), readlock(&flags).get("bot"));
+        assert!(gs.components::<String>().is_none());
+
+        // the built-in components are registered the same way
+        let scanners = gs.components::<ScannerComponent>().unwrap();
+        assert!(readlock(&scanners).contains_key("bot"));
+        gs.reset_combatant("bot");
+        assert!(readlock(&gs.damage_components).contains_key("bot"));
+    }
+}
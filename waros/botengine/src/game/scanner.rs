@@ -1,14 +1,67 @@
 use super::*;
+use crate::events::log_event;
+use crate::game::snapshot::GameSnapshot;
 use nalgebra::{Point2, Rotation2, Vector2};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sensor {
+    HitDirection,
+    Proximity,
+    RadarSweep,
+}
+
+#[derive(Debug, Clone)]
 pub struct ScannerComponent {
     pub angle: i32,
+    pub last_hit_heading: Option<i32>,
+    // cycle at which each sensor can next be used
+    pub hit_direction_ready: u32,
+    pub proximity_ready: u32,
+    pub radar_sweep_ready: u32,
+    // scans applied this cycle, waiting to be logged by the ScannerSystem.
+    // The per-cycle command cap bounds how many a bot can queue.
+    pub pending_scans: Vec<ScanRecord>,
+}
+
+/// A single `scan` call: where the bot looked and what it found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanRecord {
+    pub angle: i32,
+    pub resolution: i32,
+    pub range: i32,
 }
 
 impl ScannerComponent {
     pub fn new() -> ScannerComponent {
-        ScannerComponent { angle: 0 }
+        ScannerComponent {
+            angle: 0,
+            last_hit_heading: None,
+            hit_direction_ready: 0,
+            proximity_ready: 0,
+            radar_sweep_ready: 0,
+            pending_scans: Vec::new(),
+        }
+    }
+
+    pub fn ready(&self, sensor: Sensor, cycle: u32) -> bool {
+        cycle >= self.ready_at(sensor)
+    }
+
+    pub fn ready_at(&self, sensor: Sensor) -> u32 {
+        match sensor {
+            Sensor::HitDirection => self.hit_direction_ready,
+            Sensor::Proximity => self.proximity_ready,
+            Sensor::RadarSweep => self.radar_sweep_ready,
+        }
+    }
+
+    // Starts the sensor's cooldown
+    pub fn trigger(&mut self, sensor: Sensor, cycle: u32) {
+        match sensor {
+            Sensor::HitDirection => self.hit_direction_ready = cycle + HIT_DIRECTION_COOLDOWN,
+            Sensor::Proximity => self.proximity_ready = cycle + PROXIMITY_COOLDOWN,
+            Sensor::RadarSweep => self.radar_sweep_ready = cycle + RADAR_SWEEP_COOLDOWN,
+        }
     }
 }
 
@@ -21,34 +74,18 @@ impl ScannerSystem {
         ScannerSystem { logger }
     }
 
-    pub fn scan(game_state: &Arc<GameState>, player: &str, degree: f32, resolution: f32) -> i32 {
+    pub fn scan(snapshot: &GameSnapshot, player: &str, degree: f32, resolution: f32) -> i32 {
         let resolution = resolution.min(RES_LIMIT);
 
-        let mcs = game_state.motion_components.read().unwrap();
-        let source = mcs.get(player).unwrap();
-        let dcs = game_state.damage_components.read().unwrap();
-        let living_players: Vec<_> = game_state
-            .players
-            .read()
-            .unwrap()
-            .iter()
-            .filter_map(|p| match dcs.get(p) {
-                Some(dc) => {
-                    if dc.dead() {
-                        None
-                    } else {
-                        Some(p.to_string())
-                    }
-                }
-                None => None,
-            })
-            .collect();
+        let source = match snapshot.bot(player) {
+            Some(b) => b,
+            None => return 0,
+        };
 
-        let mut targets: Vec<_> = living_players
-            .iter()
-            .filter(|t| *t != player)
-            .filter_map(|t| {
-                let target = mcs.get(t).unwrap();
+        let mut targets: Vec<_> = snapshot
+            .living_players()
+            .filter(|(t, _)| *t != player)
+            .filter_map(|(_, target)| {
                 let heading = Self::heading_to_target(&source.position, &target.position);
                 let spread = (heading - degree).abs();
 
@@ -74,6 +111,49 @@ impl ScannerSystem {
         }
     }
 
+    // Number of living bots within close range of the player
+    pub fn proximity(snapshot: &GameSnapshot, player: &str) -> i32 {
+        let source = match snapshot.bot(player) {
+            Some(b) => b,
+            None => return 0,
+        };
+
+        snapshot
+            .living_players()
+            .filter(|(t, _)| *t != player)
+            .filter(|(_, target)| {
+                Self::range_to_target(&source.position, &target.position) <= PROXIMITY_RANGE
+            })
+            .count() as i32
+    }
+
+    // (user heading, range) of living bots within scanner range, nearest
+    // first, bounded to RADAR_MAX_CONTACTS
+    pub fn radar_sweep(snapshot: &GameSnapshot, player: &str) -> Vec<(i32, i32)> {
+        let source = match snapshot.bot(player) {
+            Some(b) => b,
+            None => return Vec::new(),
+        };
+
+        let mut contacts: Vec<_> = snapshot
+            .living_players()
+            .filter(|(t, _)| *t != player)
+            .filter_map(|(_, target)| {
+                let r = Self::range_to_target(&source.position, &target.position);
+                if r <= SCAN_MAX_RANGE {
+                    let h = Self::heading_to_target(&source.position, &target.position);
+                    Some((Self::to_user_heading(h), r as i32))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        contacts.sort_by_key(|&(_, r)| r);
+        contacts.truncate(RADAR_MAX_CONTACTS);
+        contacts
+    }
+
     pub fn heading_to_target(source: &Point2<f32>, target: &Point2<f32>) -> f32 {
         let heading = Rotation2::rotation_between(&Vector2::x(), &(target - source));
         heading.angle().to_degrees()
@@ -97,11 +177,38 @@ impl ScannerSystem {
 }
 
 impl System for ScannerSystem {
-    fn apply(&self, _cycle: u32, _game_state: &Arc<GameState>) {}
+    fn apply(&self, cycle: u32, game_state: &Arc<GameState>) {
+        let players = game_state.players.read().unwrap();
+        let mut scs = writelock(&game_state.scanner_components);
+
+        for player in players.iter() {
+            let scans = scs
+                .get_mut(player)
+                .map_or(Vec::new(), |sc| std::mem::take(&mut sc.pending_scans));
+            for scan in scans {
+                log_event(
+                    &self.logger,
+                    GameEvent::Scan {
+                        cycle,
+                        player: player.to_string(),
+                        angle: scan.angle,
+                        resolution: scan.resolution,
+                        range: scan.range,
+                    },
+                );
+            }
+        }
+    }
 }
 
 pub const RES_LIMIT: f32 = 10.0;
 const SCAN_MAX_RANGE: f32 = 700.0;
+const PROXIMITY_RANGE: f32 = 100.0;
+pub const RADAR_MAX_CONTACTS: usize = 8;
+
+const HIT_DIRECTION_COOLDOWN: u32 = 5;
+const PROXIMITY_COOLDOWN: u32 = 10;
+const RADAR_SWEEP_COOLDOWN: u32 = 50;
 
 #[cfg(test)]
 mod test {
@@ -161,6 +268,68 @@ mod test {
         );
     }
 
+    #[test]
+    fn radar_sweep_and_proximity() {
+        let gs = GameState::new();
+        let bots = [
+            ("me", 100.0_f32, 100.0_f32),
+            ("near", 150.0, 100.0),
+            ("far", 100.0, 600.0),
+        ];
+        for (name, x, y) in bots.iter() {
+            gs.combatant_entered(name);
+            writelock(&gs.motion_components)
+                .entry(name.to_string())
+                .and_modify(|mc| mc.position = Point2::new(*x, *y));
+        }
+        gs.publish_snapshot(1);
+        let snapshot = gs.snapshot.load();
+
+        assert_eq!(
+            vec![(0, 50), (90, 500)],
+            ScannerSystem::radar_sweep(&snapshot, "me")
+        );
+        assert_eq!(1, ScannerSystem::proximity(&snapshot, "me"));
+    }
+
+    #[test]
+    fn applied_scans_are_logged() {
+        use crate::game::commands::BotCommand;
+        use std::sync::mpsc::channel;
+
+        let gs = Arc::new(GameState::new());
+        gs.combatant_entered("me");
+        // every scan of the cycle is logged, in the order made
+        for angle in [90, 45].iter() {
+            gs.commands.push(
+                "me",
+                BotCommand::Scan {
+                    angle: *angle,
+                    resolution: 10,
+                    range: 300,
+                },
+            );
+        }
+        gs.commands.apply_pending(&gs, &None);
+
+        let (sender, receiver) = channel();
+        ScannerSystem::new(Some(sender)).apply(3, &gs);
+        for expected in [90, 45].iter() {
+            match receiver.try_recv() {
+                Ok(GameEvent::Scan {
+                    cycle: 3,
+                    ref player,
+                    angle,
+                    resolution: 10,
+                    range: 300,
+                }) if player == "me" && angle == *expected => {}
+                other => panic!("unexpected scan event: {:?}", other),
+            }
+        }
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(45, readlock(&gs.scanner_components)["me"].angle);
+    }
+
     #[test]
     fn distance_to_target() {
         let source = Point2::new(0.0f32, 0.0);
@@ -0,0 +1,82 @@
+use super::*;
+use crate::game::objectives::{GameMode, ObjectiveState};
+use nalgebra::Point2;
+
+/// Immutable view of the game published by the game loop at the end of
+/// every cycle. Bot host calls read from the latest snapshot so they never
+/// block on the component locks used by the systems.
+#[derive(Debug)]
+pub struct GameSnapshot {
+    pub cycle: u32,
+    pub players: Vec<String>,
+    pub bots: HashMap<String, BotSnapshot>,
+    pub objectives: ObjectiveState,
+}
+
+#[derive(Debug, Clone)]
+pub struct BotSnapshot {
+    pub position: Point2<f32>,
+    pub heading: i32,
+    pub speed: i32,
+    pub damage: u32,
+    pub dead: bool,
+    pub can_fire: bool,
+    pub scanner: ScannerComponent,
+}
+
+impl GameSnapshot {
+    pub fn empty() -> GameSnapshot {
+        GameSnapshot {
+            cycle: 0,
+            players: Vec::new(),
+            bots: HashMap::new(),
+            objectives: ObjectiveState::new(GameMode::CycleLimit),
+        }
+    }
+
+    // Copies the back buffer (the component hashes) into a new snapshot
+    pub fn capture(game_state: &GameState, cycle: u32) -> GameSnapshot {
+        let players = game_state.players.read().unwrap().clone();
+        let mcs = readlock(&game_state.motion_components);
+        let dcs = readlock(&game_state.damage_components);
+        let pcs = readlock(&game_state.projectile_components);
+        let scs = readlock(&game_state.scanner_components);
+
+        let bots = players
+            .iter()
+            .filter_map(|p| match (mcs.get(p), dcs.get(p), pcs.get(p), scs.get(p)) {
+                (Some(mc), Some(dc), Some(pc), Some(sc)) => Some((
+                    p.to_string(),
+                    BotSnapshot {
+                        position: mc.position.clone(),
+                        heading: mc.heading,
+                        speed: mc.speed,
+                        damage: dc.damage,
+                        dead: dc.dead(),
+                        can_fire: pc.ready(),
+                        scanner: sc.clone(),
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+
+        GameSnapshot {
+            cycle,
+            players,
+            bots,
+            objectives: game_state.objectives.read().unwrap().clone(),
+        }
+    }
+
+    pub fn bot(&self, player: &str) -> Option<&BotSnapshot> {
+        self.bots.get(player)
+    }
+
+    pub fn living_players<'a>(&'a self) -> impl Iterator<Item = (&'a String, &'a BotSnapshot)> {
+        self.players
+            .iter()
+            .filter_map(move |p| self.bots.get(p).map(|b| (p, b)))
+            .filter(|(_, b)| !b.dead)
+    }
+}
@@ -1,14 +1,32 @@
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 use wasmi::{HostError, ImportsBuilder, Module, ModuleInstance, ModuleRef};
 
-pub use crate::game::{GameState, Gameloop};
+pub use crate::events::{log_event, GameEvent};
+pub use crate::game::objectives::GameMode;
+pub use crate::game::{
+    readlock, writelock, ComponentHash, GameState, Gameloop, GameloopBuilder,
+    LoopTerminationReason, System, DAMAGE_SYSTEM, MOTION_SYSTEM, OBJECTIVE_SYSTEM,
+    PROJECTILE_SYSTEM, SCANNER_SYSTEM,
+};
+pub use crate::admission::{decode_signature, split_signature, SIGNATURE_SECTION};
+#[cfg(feature = "signed-bots")]
+pub use crate::admission::TrustedKeys;
+pub use crate::limits::BotLimits;
 pub use crate::runtime::{Runtime, BOTINIT_NAME};
+use crate::game::commands::BotCommand;
+use crate::runtime::wasi::{self, CompatImportResolver};
 
 pub struct Combatant {}
 
+// How long `BotHandle::stop` waits for a bot thread before giving up on it
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
 impl Combatant {
     pub fn buffer_from_file(path: &str) -> Result<Vec<u8>> {
         use std::fs::File;
@@ -23,30 +41,307 @@ impl Combatant {
         name: &str,
         buffer: Vec<u8>,
         game_state: Arc<crate::game::GameState>, // (1)
-    ) -> JoinHandle<()> { // (2)
+    ) -> BotHandle { // (2)
+        Self::start_with_options(name, buffer, game_state, BotOptions::default())
+    }
+
+    pub fn start_with_options(
+        name: &str,
+        buffer: Vec<u8>,
+        game_state: Arc<crate::game::GameState>,
+        options: BotOptions,
+    ) -> BotHandle {
         let n = name.to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let bot_stop = stop.clone();
+        let bot_state = game_state.clone();
+        let bot_options = options.clone();
+        // dropped when the thread exits, however it exits
+        let (exited, done) = mpsc::channel::<()>();
 
-        thread::spawn(move || {
-            let module = Module::from_buffer(&buffer).unwrap();
-            let mut runtime = runtime::Runtime::init(game_state, n.clone()); // (3)
+        let thread = thread::spawn(move || {
+            let _exited = exited;
+            let limits = bot_options.limits;
+            let module = match bot_options.load_module(&buffer) {
+                Ok(m) => m,
+                Err(e) => {
+                    bot_state.combatant_failed(&n);
+                    return Self::report_fault(&bot_state, &n, e.to_string());
+                }
+            };
+            let fault_state = bot_state.clone();
+            let mut runtime =
+                runtime::Runtime::init(bot_state, n.clone(), bot_stop); // (3)
             let moduleref =
-                Self::get_module_instance_from_module(&module).unwrap();
+                match Self::get_module_instance_from_module(&module, &bot_options) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        // entered alive by Runtime::init, so it must be
+                        // taken out of the match again
+                        fault_state.combatant_failed(&n);
+                        return Self::report_fault(&fault_state, &n, e.to_string());
+                    }
+                };
+            runtime.set_memory(
+                moduleref
+                    .export_by_name("memory")
+                    .and_then(|e| e.as_memory().cloned()),
+            );
             let res = // (4)
-                moduleref.invoke_export(BOTINIT_NAME, &[][..], &mut runtime);
+                Self::run_entry_point(&moduleref, &bot_options, &mut runtime);
             println!("bot init loop exited for player {} - {:?}", n, res);
-        })
+
+            if let Err(ref e) = res {
+                if is_fault(e) {
+                    let mut reason = host_error(e).map_or(e.to_string(), |h| h.to_string());
+                    if runtime.memory_pages().map_or(false, |p| p >= limits.memory_pages) {
+                        reason.push_str(&format!(
+                            " (memory limit of {} pages reached)",
+                            limits.memory_pages
+                        ));
+                    }
+                    Self::report_fault(&fault_state, &n, reason);
+                }
+            }
+        });
+
+        BotHandle {
+            name: name.to_string(),
+            game_state,
+            options,
+            stop,
+            thread,
+            done,
+        }
+    }
+
+    // Stops a running bot and starts a new module under the same player
+    // name, without interrupting the game loop. The new module is validated,
+    // and its signature checked when the match has trusted keys, before the
+    // running bot is stopped, so a bad buffer leaves the old bot in place.
+    // A detached signature given for the old module is not reused; use
+    // `replace_with_options` to pass one for the new module.
+    pub fn replace(
+        handle: BotHandle,
+        buffer: Vec<u8>,
+        policy: RespawnPolicy,
+    ) -> std::result::Result<BotHandle, (BotHandle, Error)> {
+        let options = handle.options.for_new_module();
+        Self::replace_with_options(handle, buffer, options, policy)
     }
-    fn get_module_instance_from_module(module: &Module) -> Result<ModuleRef> {
+
+    // Like `replace`, running the new module with different options
+    pub fn replace_with_options(
+        handle: BotHandle,
+        buffer: Vec<u8>,
+        options: BotOptions,
+        policy: RespawnPolicy,
+    ) -> std::result::Result<BotHandle, (BotHandle, Error)> {
+        if let Err(e) = options.load_module(&buffer) {
+            return Err((handle, e));
+        }
+
+        let name = handle.name.clone();
+        let game_state = handle.game_state.clone();
+        handle.stop();
+
+        if let RespawnPolicy::Reset = policy {
+            game_state.reset_combatant(&name);
+        }
+        game_state.clock.resume(&name);
+        Ok(Self::start_with_options(&name, buffer, game_state, options))
+    }
+
+    fn get_module_instance_from_module(
+        module: &Module,
+        options: &BotOptions,
+    ) -> Result<ModuleRef> {
+        // without the `wasi` option the stubs are refused by name, so the
+        // error says which option the bot needs
+        let env = CompatImportResolver::new(wasi::ENV_MODULE, options.wasi);
+        let wasi_preview1 = CompatImportResolver::new(wasi::WASI_MODULE, options.wasi);
+        let wasi_unstable = CompatImportResolver::new(wasi::WASI_UNSTABLE_MODULE, options.wasi);
+        let wbindgen = CompatImportResolver::new(wasi::WBINDGEN_MODULE, options.wasi);
+
         let mut imports = ImportsBuilder::new();
-        imports.push_resolver("env", &runtime::RuntimeModuleImportResolver);
+        imports.push_resolver(runtime::STOP_CHECK_MODULE, &runtime::StopCheckImportResolver);
+        if options.wasi {
+            imports.push_resolver(wasi::ENV_MODULE, &env);
+        } else {
+            imports.push_resolver("env", &runtime::RuntimeModuleImportResolver);
+        }
+        imports.push_resolver(wasi::WASI_MODULE, &wasi_preview1);
+        imports.push_resolver(wasi::WASI_UNSTABLE_MODULE, &wasi_unstable);
+        imports.push_resolver(wasi::WBINDGEN_MODULE, &wbindgen);
+
+        Ok(ModuleInstance::new(module, &imports)?.assert_no_start())
+    }
+
+    // Runs `botinit`. WASI reactors get `_initialize` first, and command
+    // modules without a `botinit` run their `_start`.
+    fn run_entry_point(
+        moduleref: &ModuleRef,
+        options: &BotOptions,
+        runtime: &mut Runtime,
+    ) -> std::result::Result<Option<wasmi::RuntimeValue>, wasmi::Error> {
+        let mut stack = options.limits.stack();
+        if !options.wasi {
+            return moduleref.invoke_export_with_stack(
+                BOTINIT_NAME,
+                &[][..],
+                runtime,
+                &mut stack,
+            );
+        }
 
-        Ok(ModuleInstance::new(module, &imports)
-            .expect("Failed to instantiate module")
-            .assert_no_start())
+        if moduleref.export_by_name(wasi::WASI_INITIALIZE_NAME).is_some() {
+            moduleref.invoke_export_with_stack(
+                wasi::WASI_INITIALIZE_NAME,
+                &[][..],
+                runtime,
+                &mut stack,
+            )?;
+        }
+        let entry = if moduleref.export_by_name(BOTINIT_NAME).is_some() {
+            BOTINIT_NAME
+        } else {
+            wasi::WASI_START_NAME
+        };
+        moduleref.invoke_export_with_stack(entry, &[][..], runtime, &mut stack)
+    }
+
+    // Bot faults show up in the game log as `BotFault` events
+    fn report_fault(game_state: &crate::game::GameState, name: &str, reason: String) {
+        println!("bot {} faulted: {}", name, reason);
+        game_state
+            .commands
+            .push(name, BotCommand::Fault { reason });
     }
 }
 
+// The botengine error a host call trapped the bot with, if any
+fn host_error(e: &wasmi::Error) -> Option<&Error> {
+    match e {
+        wasmi::Error::Trap(trap) => match trap.kind() {
+            wasmi::TrapKind::Host(h) => h.downcast_ref::<Error>(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether a bot ending with this error should be reported as a fault,
+// rather than having been stopped or having exited cleanly
+fn is_fault(e: &wasmi::Error) -> bool {
+    match host_error(e).map(|h| &h.kind) {
+        Some(Kind::BotStopped(_)) | Some(Kind::BotExited(_, 0)) => false,
+        _ => true,
+    }
+}
+
+/// Per-bot settings for `Combatant::start_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct BotOptions {
+    /// Link stubs for the WASI, AssemblyScript and wasm-bindgen imports so
+    /// bots built with those toolchains load unmodified
+    pub wasi: bool,
+    /// Memory and stack caps for the bot's instance
+    pub limits: BotLimits,
+    /// Keys the bot must be signed by. Without any, bots load unchecked.
+    #[cfg(feature = "signed-bots")]
+    pub trusted_keys: Option<Arc<TrustedKeys>>,
+    /// Detached signature for the module, raw or hex encoded. Without one
+    /// the module must carry a signature section.
+    #[cfg(feature = "signed-bots")]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl BotOptions {
+    // Options for an unsigned bot
+    pub fn new(wasi: bool, limits: BotLimits) -> BotOptions {
+        BotOptions {
+            wasi,
+            limits,
+            #[cfg(feature = "signed-bots")]
+            trusted_keys: None,
+            #[cfg(feature = "signed-bots")]
+            signature: None,
+        }
+    }
 
+    // Checks the module's signature, if there are trusted keys, and loads
+    // it within the limits
+    fn load_module(&self, buffer: &[u8]) -> Result<Module> {
+        #[cfg(feature = "signed-bots")]
+        {
+            if let Some(ref keys) = self.trusted_keys {
+                keys.admit(buffer, self.signature.as_ref().map(|s| &s[..]))?;
+            }
+        }
+        self.limits.load_module(buffer)
+    }
+
+    // The same options for a different module. A detached signature only
+    // covers the module it was made for, so it is dropped.
+    fn for_new_module(&self) -> BotOptions {
+        BotOptions {
+            #[cfg(feature = "signed-bots")]
+            signature: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// What happens to a player's components when its bot is replaced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RespawnPolicy {
+    /// Keep position, damage, projectiles and scores as they are
+    Preserve,
+    /// Re-enter as a fresh combatant at its spawn position with no damage,
+    /// no score and without any flag it was carrying
+    Reset,
+}
+
+/// A running bot thread
+pub struct BotHandle {
+    name: String,
+    game_state: Arc<crate::game::GameState>,
+    options: BotOptions,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+    done: Receiver<()>,
+}
+
+impl BotHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Asks the bot to stop and waits up to STOP_TIMEOUT for its thread to
+    // exit. The bot traps on its next host call, and every loop in a bot
+    // makes one, so even a bot spinning in its own code stops. Should the
+    // thread still not exit in time it is detached and the player reported
+    // as faulted.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.game_state.clock.interrupt(&self.name);
+        if let Err(RecvTimeoutError::Timeout) = self.done.recv_timeout(STOP_TIMEOUT) {
+            Combatant::report_fault(
+                &self.game_state,
+                &self.name,
+                format!("did not stop within {:?}, detached", STOP_TIMEOUT),
+            );
+            return;
+        }
+        if self.thread.join().is_err() {
+            println!("bot thread for player {} panicked", self.name);
+        }
+    }
+
+    pub fn join(self) -> thread::Result<()> {
+        self.thread.join()
+    }
+}
 
 /// A botengine error
 #[derive(Debug)]
@@ -76,6 +371,12 @@ impl fmt::Display for Error {
             Kind::MiscFailure(ref s) => fmt::Display::fmt(s, f),
             Kind::IoError(ref s) => fmt::Display::fmt(s, f),
             Kind::ExportResolve(ref s) => fmt::Display::fmt(s, f),
+            Kind::BotStopped(ref s) => write!(f, "bot {} was stopped", s),
+            Kind::BotExited(ref s, code) => {
+                write!(f, "bot {} exited with code {}", s, code)
+            }
+            Kind::LimitExceeded(ref s) => fmt::Display::fmt(s, f),
+            Kind::AdmissionRefused(ref s) => write!(f, "bot refused: {}", s),
         }
     }
 }
@@ -104,11 +405,264 @@ pub enum Kind {
     IoError(std::io::Error),
     ExportResolve(String),
     MiscFailure(String),
+    BotStopped(String),
+    BotExited(String, i32),
+    LimitExceeded(String),
+    AdmissionRefused(String),
 }
 
 /// A Result where failure is a botengine error
 pub type Result<T> = std::result::Result<T, Error>;
 
+mod admission;
 mod events;
 mod game;
+mod limits;
 mod runtime;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::commands::BotCommand;
+    use parity_wasm::builder;
+    use parity_wasm::elements::{BlockType, Instruction, Instructions};
+
+    // A module whose `botinit` runs the given instructions
+    fn bot(code: Vec<Instruction>) -> Vec<u8> {
+        let module = builder::module()
+            .function()
+            .signature()
+            .build()
+            .body()
+            .with_instructions(Instructions::new(code))
+            .build()
+            .build()
+            .export()
+            .field(BOTINIT_NAME)
+            .internal()
+            .func(0)
+            .build()
+            .build();
+        parity_wasm::serialize(module).unwrap()
+    }
+
+    fn idle() -> Vec<u8> {
+        bot(vec![Instruction::End])
+    }
+
+    #[test]
+    fn replace_preserves_or_resets() {
+        for &policy in [RespawnPolicy::Preserve, RespawnPolicy::Reset].iter() {
+            let gs = Arc::new(GameState::new());
+            gs.set_mode(GameMode::king_of_the_hill(500.0, 500.0, 50.0, 100));
+            gs.combatant_entered("bot");
+            let handle = Combatant::start("bot", idle(), gs.clone());
+
+            writelock(&gs.damage_components).get_mut("bot").unwrap().damage = 40;
+            {
+                let mut os = gs.objectives.write().unwrap();
+                os.scores.insert("bot".to_string(), 7);
+                os.holder = Some("bot".to_string());
+            }
+
+            let handle = match Combatant::replace(handle, idle(), policy) {
+                Ok(h) => h,
+                Err((_, e)) => panic!("replace failed: {}", e),
+            };
+            let damage = readlock(&gs.damage_components)["bot"].damage;
+            let os = gs.objectives.read().unwrap().clone();
+            match policy {
+                RespawnPolicy::Preserve => {
+                    assert_eq!(40, damage);
+                    assert_eq!(7, os.score("bot"));
+                    assert_eq!(Some("bot".to_string()), os.holder);
+                }
+                RespawnPolicy::Reset => {
+                    assert_eq!(0, damage);
+                    assert_eq!(0, os.score("bot"));
+                    assert_eq!(None, os.holder);
+                }
+            }
+            assert_eq!(vec!["bot".to_string()], *gs.players.read().unwrap());
+            handle.stop();
+        }
+    }
+
+    #[test]
+    fn unloadable_bot_enters_dead() {
+        let gs = Arc::new(GameState::new());
+        Combatant::start("broken", vec![0, 1, 2, 3], gs.clone())
+            .join()
+            .unwrap();
+
+        // valid, but importing something the host doesn't provide
+        let unlinkable = wat::parse_str(
+            r#"(module
+                (import "env" "teleport" (func $teleport))
+                (func (export "botinit") (call $teleport)))"#,
+        )
+        .unwrap();
+        run_bot(&gs, "unlinkable", unlinkable, BotOptions::default());
+
+        assert_eq!(
+            vec!["broken".to_string(), "unlinkable".to_string()],
+            *gs.players.read().unwrap()
+        );
+        let dcs = readlock(&gs.damage_components);
+        assert!(dcs["broken"].dead());
+        assert!(dcs["unlinkable"].dead());
+    }
+
+    // Runs a bot until its thread exits, advancing the clock so it gets
+    // past entering the game
+    fn run_bot(gs: &Arc<GameState>, name: &str, wasm: Vec<u8>, options: BotOptions) {
+        let handle = Combatant::start_with_options(name, wasm, gs.clone(), options);
+        let tick = Duration::from_millis(10);
+        while let Err(RecvTimeoutError::Timeout) = handle.done.recv_timeout(tick) {
+            gs.clock.tick();
+        }
+        handle.join().unwrap();
+    }
+
+    // Runs a bot given as WAT and returns the commands it queued
+    fn run_to_end(wat: &str, wasi: bool) -> Vec<BotCommand> {
+        let gs = Arc::new(GameState::new());
+        let options = BotOptions::new(wasi, BotLimits::default());
+        run_bot(&gs, "bot", wat::parse_str(wat).unwrap(), options);
+        gs.commands.drain().into_iter().map(|(_, c)| c).collect()
+    }
+
+    fn faults(commands: &[BotCommand]) -> Vec<&str> {
+        commands
+            .iter()
+            .filter_map(|c| match c {
+                BotCommand::Fault { reason } => Some(reason.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // A WASI command module that prints and then exits with `code`
+    fn wasi_command(code: i32) -> String {
+        format!(
+            r#"(module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 16) "hi\n")
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 3))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (call $proc_exit (i32.const {}))))"#,
+            code
+        )
+    }
+
+    #[test]
+    fn wasi_commands_run_start() {
+        let commands = run_to_end(&wasi_command(0), true);
+        assert!(faults(&commands).is_empty());
+        assert!(commands.iter().any(|c| match c {
+            BotCommand::Output { text } => text == "hi",
+            _ => false,
+        }));
+
+        let faulted = run_to_end(&wasi_command(3), true);
+        assert_eq!(vec!["bot bot exited with code 3"], faults(&faulted));
+    }
+
+    #[test]
+    fn compat_imports_need_wasi() {
+        let faulted = run_to_end(&wasi_command(0), false);
+        let reasons = faults(&faulted);
+        assert_eq!(1, reasons.len());
+        assert!(reasons[0].contains("wasi_snapshot_preview1::fd_write"));
+        assert!(reasons[0].contains("wasi option"));
+
+        // AssemblyScript's abort sits next to the bot API in `env`
+        let assemblyscript = r#"(module
+            (import "env" "abort" (func $abort (param i32 i32 i32 i32)))
+            (import "env" "loc_x" (func $loc_x (result i32)))
+            (memory (export "memory") 1)
+            (func (export "botinit")
+                (call $abort (i32.const 0) (i32.const 0) (i32.const 7) (i32.const 3))))"#;
+        let reasons = faults(&run_to_end(assemblyscript, false)).join("");
+        assert!(reasons.contains("env::abort") && reasons.contains("wasi option"));
+        let reasons = faults(&run_to_end(assemblyscript, true)).join("");
+        assert!(reasons.contains("aborted") && reasons.contains(":7:3"));
+    }
+
+    #[cfg(feature = "signed-bots")]
+    #[test]
+    fn signatures_are_checked_on_load() {
+        use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+        let mut keys = TrustedKeys::new();
+        keys.add(&public.to_bytes()).unwrap();
+
+        let wasm = idle();
+        let options = BotOptions {
+            trusted_keys: Some(Arc::new(keys)),
+            signature: Some(keypair.sign(&wasm).to_bytes().to_vec()),
+            ..BotOptions::default()
+        };
+        assert!(options.load_module(&wasm).is_ok());
+
+        // still a valid module, with an extra custom section named "x"
+        let mut tampered = wasm.clone();
+        tampered.extend_from_slice(&[0x00, 0x02, 0x01, b'x']);
+        assert!(BotOptions::default().load_module(&tampered).is_ok());
+        match options.load_module(&tampered) {
+            Err(Error {
+                kind: Kind::AdmissionRefused(_),
+            }) => {}
+            other => panic!("tampered module was not refused: {:?}", other.err()),
+        }
+
+        // a tampered bot enters the match dead, and can't replace a signed one
+        let gs = Arc::new(GameState::new());
+        Combatant::start_with_options("tampered", tampered.clone(), gs.clone(), options.clone())
+            .join()
+            .unwrap();
+        assert!(readlock(&gs.damage_components)["tampered"].dead());
+
+        let handle = Combatant::start_with_options("bot", wasm, gs, options);
+        match Combatant::replace(handle, tampered, RespawnPolicy::Preserve) {
+            Ok(_) => panic!("tampered replacement was loaded"),
+            Err((handle, _)) => handle.stop(),
+        }
+    }
+
+    #[test]
+    fn runaway_bot_stops() {
+        let gs = Arc::new(GameState::new());
+        let spin = bot(vec![
+            Instruction::Loop(BlockType::NoResult),
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End,
+        ]);
+        let handle = Combatant::start("spinner", spin, gs.clone());
+        // let it get into the loop
+        gs.publish_snapshot(1);
+        gs.clock.tick();
+        thread::sleep(Duration::from_millis(50));
+        handle.stop();
+
+        let faults = gs
+            .commands
+            .drain()
+            .into_iter()
+            .filter(|(_, c)| match c {
+                BotCommand::Fault { .. } => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(0, faults);
+    }
+}
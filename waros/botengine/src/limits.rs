@@ -0,0 +1,260 @@
+use crate::runtime::{STOP_CHECK_MODULE, STOP_CHECK_NAME};
+use crate::{Error, Kind, Result};
+use parity_wasm::elements::{
+    External, FunctionType, ImportCountType, ImportEntry, ImportSection, Instruction, Internal,
+    MemoryType, Module as RawModule, Section, Type,
+};
+use wasmi::{Module, StackRecycler};
+
+/// Resource caps for a single bot instance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BotLimits {
+    /// Most 64KiB pages of linear memory the bot can grow into
+    pub memory_pages: u32,
+    /// Deepest call stack, in frames
+    pub call_depth: usize,
+    /// Size of the operand stack, in bytes
+    pub value_stack_bytes: usize,
+}
+
+// 64MiB of linear memory
+pub const DEFAULT_MEMORY_PAGES: u32 = 1024;
+
+impl Default for BotLimits {
+    fn default() -> BotLimits {
+        BotLimits {
+            memory_pages: DEFAULT_MEMORY_PAGES,
+            call_depth: wasmi::DEFAULT_CALL_STACK_LIMIT,
+            value_stack_bytes: wasmi::DEFAULT_VALUE_STACK_LIMIT,
+        }
+    }
+}
+
+impl BotLimits {
+    // Loads a module, refusing it if its initial memory is over the page
+    // cap. The declared memory maximum is lowered to the cap, so any
+    // `memory.grow` past it fails inside the bot. Every loop is made to
+    // check whether the bot has been stopped.
+    pub fn load_module(&self, buffer: &[u8]) -> Result<Module> {
+        let mut raw: RawModule = parity_wasm::deserialize_buffer(buffer).map_err(invalid)?;
+        self.cap_memory(&mut raw)?;
+        add_stop_checks(&mut raw)?;
+        Ok(Module::from_parity_wasm_module(raw)?)
+    }
+
+    pub fn stack(&self) -> StackRecycler {
+        StackRecycler::with_limits(self.value_stack_bytes, self.call_depth)
+    }
+
+    fn cap_memory(&self, raw: &mut RawModule) -> Result<()> {
+        if let Some(section) = raw.memory_section_mut() {
+            for memory in section.entries_mut().iter_mut() {
+                *memory = self.capped(memory)?;
+            }
+        }
+        if let Some(section) = raw.import_section_mut() {
+            for entry in section.entries_mut().iter_mut() {
+                if let External::Memory(ref mut memory) = *entry.external_mut() {
+                    *memory = self.capped(memory)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn capped(&self, memory: &MemoryType) -> Result<MemoryType> {
+        let limits = memory.limits();
+        if limits.initial() > self.memory_pages {
+            return Err(Error {
+                kind: Kind::LimitExceeded(format!(
+                    "module starts with {} pages of memory, the limit is {}",
+                    limits.initial(),
+                    self.memory_pages
+                )),
+            });
+        }
+        let maximum = limits
+            .maximum()
+            .map_or(self.memory_pages, |m| m.min(self.memory_pages));
+        Ok(MemoryType::new(limits.initial(), Some(maximum)))
+    }
+}
+
+fn invalid(e: parity_wasm::elements::Error) -> Error {
+    Error::from(wasmi::Error::Validation(format!("invalid module: {}", e)))
+}
+
+// Imports the runtime's stop check and calls it at the top of every loop
+// body. A bot spinning without host calls then traps on its next iteration
+// once stopped, instead of running on in a detached thread.
+fn add_stop_checks(raw: &mut RawModule) -> Result<()> {
+    let is_loop = |i: &Instruction| match i {
+        Instruction::Loop(_) => true,
+        _ => false,
+    };
+    let has_loops = raw.code_section().map_or(false, |code| {
+        code.bodies()
+            .iter()
+            .any(|b| b.code().elements().iter().any(is_loop))
+    });
+    if !has_loops {
+        return Ok(());
+    }
+
+    // the check goes after the existing function imports, moving every
+    // function defined in the module up one index
+    let check = raw.import_count(ImportCountType::Function) as u32;
+    let shift = |index: &mut u32| {
+        if *index >= check {
+            *index += 1
+        }
+    };
+
+    let signature = Type::Function(FunctionType::new(vec![], None));
+    let types = raw
+        .type_section_mut()
+        .ok_or_else(|| invalid(parity_wasm::elements::Error::Other("no type section")))?
+        .types_mut();
+    let type_index = match types.iter().position(|t| *t == signature) {
+        Some(i) => i,
+        None => {
+            types.push(signature);
+            types.len() - 1
+        }
+    } as u32;
+
+    if raw.import_section().is_none() {
+        raw.insert_section(Section::Import(ImportSection::with_entries(vec![])))
+            .map_err(invalid)?;
+    }
+    if let Some(section) = raw.import_section_mut() {
+        section.entries_mut().push(ImportEntry::new(
+            STOP_CHECK_MODULE.to_string(),
+            STOP_CHECK_NAME.to_string(),
+            External::Function(type_index),
+        ));
+    }
+
+    if let Some(section) = raw.code_section_mut() {
+        for body in section.bodies_mut().iter_mut() {
+            let code = body.code_mut().elements_mut();
+            let mut checked = Vec::with_capacity(code.len());
+            for mut instruction in code.drain(..) {
+                if let Instruction::Call(ref mut index) = instruction {
+                    shift(index);
+                }
+                let looping = is_loop(&instruction);
+                checked.push(instruction);
+                if looping {
+                    checked.push(Instruction::Call(check));
+                }
+            }
+            *code = checked;
+        }
+    }
+    if let Some(section) = raw.export_section_mut() {
+        for entry in section.entries_mut().iter_mut() {
+            if let Internal::Function(ref mut index) = *entry.internal_mut() {
+                shift(index);
+            }
+        }
+    }
+    if let Some(section) = raw.elements_section_mut() {
+        for segment in section.entries_mut().iter_mut() {
+            segment.members_mut().iter_mut().for_each(|m| shift(m));
+        }
+    }
+    if let Some(mut start) = raw.start_section() {
+        shift(&mut start);
+        raw.set_start_section(start);
+    }
+    // function names would now be off by one
+    raw.clear_custom_section("name");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parity_wasm::elements::{
+        BlockType, CodeSection, ExportEntry, ExportSection, Func, FuncBody, FunctionSection,
+        Instructions, MemorySection, TypeSection, ValueType,
+    };
+
+    fn module_with_memory(initial: u32, maximum: Option<u32>) -> RawModule {
+        RawModule::new(vec![Section::Memory(MemorySection::with_entries(vec![
+            MemoryType::new(initial, maximum),
+        ]))])
+    }
+
+    fn maximum(raw: &RawModule) -> Option<u32> {
+        raw.memory_section().unwrap().entries()[0]
+            .limits()
+            .maximum()
+    }
+
+    #[test]
+    fn memory_is_capped() {
+        let limits = BotLimits {
+            memory_pages: 32,
+            ..BotLimits::default()
+        };
+
+        let mut unbounded = module_with_memory(17, None);
+        limits.cap_memory(&mut unbounded).unwrap();
+        assert_eq!(Some(32), maximum(&unbounded));
+
+        let mut small = module_with_memory(1, Some(4));
+        limits.cap_memory(&mut small).unwrap();
+        assert_eq!(Some(4), maximum(&small));
+
+        assert!(limits
+            .cap_memory(&mut module_with_memory(33, None))
+            .is_err());
+    }
+
+    #[test]
+    fn loops_check_for_stop() {
+        use Instruction::*;
+
+        // an imported function, then `botinit` looping over a call to a
+        // second defined function
+        let body = |code| FuncBody::new(vec![], Instructions::new(code));
+        let mut raw = RawModule::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(FunctionType::new(vec![], Some(ValueType::I32))),
+                Type::Function(FunctionType::new(vec![], None)),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+                "env".to_string(),
+                "damage".to_string(),
+                External::Function(0),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(1), Func::new(1)])),
+            Section::Export(ExportSection::with_entries(vec![ExportEntry::new(
+                "botinit".to_string(),
+                Internal::Function(1),
+            )])),
+            Section::Code(CodeSection::with_bodies(vec![
+                body(vec![Loop(BlockType::NoResult), Call(2), Br(0), End, End]),
+                body(vec![Call(0), Drop, End]),
+            ])),
+        ]);
+        add_stop_checks(&mut raw).unwrap();
+
+        let imports = raw.import_section().unwrap().entries();
+        assert_eq!(STOP_CHECK_NAME, imports[1].field());
+        assert_eq!(External::Function(1), *imports[1].external());
+        let code = raw.code_section().unwrap().bodies();
+        assert_eq!(
+            &[Loop(BlockType::NoResult), Call(1), Call(3), Br(0), End, End][..],
+            code[0].code().elements()
+        );
+        assert_eq!(&[Call(0), Drop, End][..], code[1].code().elements());
+        assert_eq!(
+            Internal::Function(2),
+            *raw.export_section().unwrap().entries()[0].internal()
+        );
+        assert!(Module::from_parity_wasm_module(raw).is_ok());
+    }
+}
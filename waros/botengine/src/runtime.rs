@@ -1,9 +1,14 @@
-use crate::game::{readlock, scanner::ScannerSystem, writelock};
+use crate::game::commands::{BotCommand, LaunchReply};
+use crate::game::scanner::{ScannerSystem, Sensor};
+use crate::game::snapshot::GameSnapshot;
 use crate::{Error, Kind};
+use arc_swap::Guard;
 use nalgebra::Point2;
+use rand::rngs::StdRng;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use wasmi::{
-    Error as InterpreterError, Externals, FuncInstance, FuncRef,
+    Error as InterpreterError, Externals, FuncInstance, FuncRef, MemoryRef,
     ModuleImportResolver, RuntimeArgs, RuntimeValue, Signature, Trap, ValueType,
 };
 
@@ -22,7 +27,27 @@ impl<'a> ModuleImportResolver for RuntimeModuleImportResolver {
         let func_ref = gen_funcref(field_name);
         match func_ref {
             Some(fr) => Ok(fr),
-            None => Err(InterpreterError::Function(field_name.to_string())),
+            None => Err(wasi::unresolved(wasi::ENV_MODULE, field_name)),
+        }
+    }
+}
+
+/// Resolves the stop check `BotLimits::load_module` adds to every loop
+pub struct StopCheckImportResolver;
+
+impl ModuleImportResolver for StopCheckImportResolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &Signature,
+    ) -> Result<FuncRef, InterpreterError> {
+        if field_name == STOP_CHECK_NAME {
+            Ok(FuncInstance::alloc_host(
+                Signature::new(&[][..], None),
+                STOP_CHECK_INDEX,
+            ))
+        } else {
+            Err(InterpreterError::Function(field_name.to_string()))
         }
     }
 }
@@ -55,7 +80,28 @@ const ATAN_NAME: &'static str = "watan";
 const ATAN_INDEX: usize = 12;
 const PLOT_COURSE_NAME: &'static str = "plot_course";
 const PLOT_COURSE_INDEX: usize = 13;
+const WAIT_CYCLES_NAME: &'static str = "wait_cycles";
+const WAIT_CYCLES_INDEX: usize = 14;
+const WAIT_EVENT_NAME: &'static str = "wait_until_event";
+const WAIT_EVENT_INDEX: usize = 15;
+const OBJECTIVE_X_NAME: &'static str = "objective_x";
+const OBJECTIVE_X_INDEX: usize = 16;
+const OBJECTIVE_Y_NAME: &'static str = "objective_y";
+const OBJECTIVE_Y_INDEX: usize = 17;
+const OBJECTIVE_STATE_NAME: &'static str = "objective_state";
+const OBJECTIVE_STATE_INDEX: usize = 18;
+const SCORE_NAME: &'static str = "score";
+const SCORE_INDEX: usize = 19;
+const LAST_HIT_HEADING_NAME: &'static str = "last_hit_heading";
+const LAST_HIT_HEADING_INDEX: usize = 20;
+const PROXIMITY_NAME: &'static str = "proximity";
+const PROXIMITY_INDEX: usize = 21;
+const RADAR_SWEEP_NAME: &'static str = "radar_sweep";
+const RADAR_SWEEP_INDEX: usize = 22;
 pub const BOTINIT_NAME: &'static str = "botinit";
+pub const STOP_CHECK_MODULE: &str = "waros";
+pub const STOP_CHECK_NAME: &str = "check_stop";
+const STOP_CHECK_INDEX: usize = 23;
 
 // Creates a FuncRef based on the name of the function
 fn gen_funcref(name: &str) -> Option<FuncRef> {
@@ -120,6 +166,43 @@ fn gen_funcref(name: &str) -> Option<FuncRef> {
                            Some(ValueType::I32)),
             PLOT_COURSE_INDEX,
         )),
+        WAIT_CYCLES_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+            WAIT_CYCLES_INDEX,
+        )),
+        WAIT_EVENT_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+            WAIT_EVENT_INDEX,
+        )),
+        OBJECTIVE_X_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            OBJECTIVE_X_INDEX,
+        )),
+        OBJECTIVE_Y_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            OBJECTIVE_Y_INDEX,
+        )),
+        OBJECTIVE_STATE_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            OBJECTIVE_STATE_INDEX,
+        )),
+        SCORE_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            SCORE_INDEX,
+        )),
+        LAST_HIT_HEADING_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            LAST_HIT_HEADING_INDEX,
+        )),
+        PROXIMITY_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[][..], Some(ValueType::I32)),
+            PROXIMITY_INDEX,
+        )),
+        RADAR_SWEEP_NAME => Some(FuncInstance::alloc_host(
+            Signature::new(&[ValueType::I32, ValueType::I32][..],
+                           Some(ValueType::I32)),
+            RADAR_SWEEP_INDEX,
+        )),
         _ => None,
     }
 }
@@ -128,6 +211,10 @@ pub struct Runtime {
     pub game_state: Arc<super::game::GameState>,
     pub module_name: String,
     dead: bool,
+    sensors_used: Vec<(Sensor, u32)>,
+    stop: Arc<AtomicBool>,
+    memory: Option<MemoryRef>,
+    rng: StdRng,
 }
 
 impl Externals for Runtime {
@@ -136,7 +223,8 @@ impl Externals for Runtime {
         index: usize,
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap> {
-        match index {
+        self.check_stopped()?;
+        let result = match index {
             SCAN_INDEX => self.scan(args.nth(0), args.nth(1)),
             CANNON_INDEX => self.cannon(args.nth(0), args.nth(1)),
             DRIVE_INDEX => self.drive(args.nth(0), args.nth(1)),
@@ -151,10 +239,25 @@ impl Externals for Runtime {
             TAN_INDEX => self.tan(args.nth(0)),
             ATAN_INDEX => self.atan(args.nth(0)),
             PLOT_COURSE_INDEX => self.plot_course(args.nth(0), args.nth(1)),
+            WAIT_CYCLES_INDEX => self.wait_cycles(args.nth(0)),
+            WAIT_EVENT_INDEX => self.wait_until_event(args.nth(0)),
+            OBJECTIVE_X_INDEX => self.objective_x(),
+            OBJECTIVE_Y_INDEX => self.objective_y(),
+            OBJECTIVE_STATE_INDEX => self.objective_state(),
+            SCORE_INDEX => self.score(),
+            LAST_HIT_HEADING_INDEX => self.last_hit_heading(),
+            PROXIMITY_INDEX => self.proximity(),
+            RADAR_SWEEP_INDEX => self.radar_sweep(args.nth(0), args.nth(1)),
+            // a stopped bot has already trapped above
+            STOP_CHECK_INDEX => Ok(None),
+            index if index >= wasi::WASI_BASE_INDEX => self.invoke_compat(index, args),
             _ => Err(Trap::from(Error {
                 kind: Kind::MiscFailure("Invalid export index".to_string()),
             })),
-        }
+        };
+        // a stop request may have released the bot from a wait
+        self.check_stopped()?;
+        result
     }
 }
 
@@ -162,26 +265,53 @@ type WasmRuntimeResult = Result<Option<RuntimeValue>, Trap>;
 
 impl Runtime {
     pub fn init(game_state: Arc<super::game::GameState>,
-                module_name: String) -> Runtime {
+                module_name: String,
+                stop: Arc<AtomicBool>) -> Runtime {
         game_state.combatant_entered(&module_name);
+        // the bot becomes visible to host calls once the game loop has
+        // published a snapshot that includes it
+        game_state.clock.wait_cycles(&module_name, 1);
+        let rng = game_state.bot_rng(&module_name);
         Runtime {
             game_state,
             module_name,
             dead: false,
+            sensors_used: Vec::new(),
+            stop,
+            memory: None,
+            rng,
+        }
+    }
+
+    // Linear memory of the bot's module instance, needed by host calls that
+    // write results back to the bot
+    pub fn set_memory(&mut self, memory: Option<MemoryRef>) {
+        self.memory = memory;
+    }
+
+    // Current size of the bot's linear memory, in 64KiB pages
+    pub fn memory_pages(&self) -> Option<u32> {
+        self.memory.as_ref().map(|m| m.current_size().0 as u32)
+    }
+
+    fn check_stopped(&self) -> Result<(), Trap> {
+        if self.stop.load(Ordering::SeqCst) {
+            Err(Trap::from(Error {
+                kind: Kind::BotStopped(self.module_name.to_string()),
+            }))
+        } else {
+            Ok(())
         }
     }
 
+    fn snapshot(&self) -> Guard<'static, Arc<GameSnapshot>> {
+        self.game_state.snapshot.load()
+    }
+
     fn is_dead(&mut self) -> bool {
         if !self.dead {
-            let dcs = self.game_state.damage_components.read().unwrap();
-            let dc = dcs.get(&self.module_name);
-            match dc {
-                Some(d) => {
-                    if let crate::game::damage::DamageStatus::Dead = d.status {
-                        self.dead = true
-                    }
-                }
-                None => {}
+            if let Some(b) = self.snapshot().bot(&self.module_name) {
+                self.dead = b.dead;
             }
         }
 
@@ -199,13 +329,18 @@ impl Runtime {
 
         let degree = angle as f32;
 
-        writelock(&self.game_state.scanner_components)
-            .entry(self.module_name.to_string())
-            .and_modify(|sc| sc.angle = degree as i32);
-
         let scan_result: i32 =
-            ScannerSystem::scan(&self.game_state, &self.module_name,
+            ScannerSystem::scan(&self.snapshot(), &self.module_name,
                                 degree, resolution);
+
+        self.game_state.commands.push(
+            &self.module_name,
+            BotCommand::Scan {
+                angle: degree as i32,
+                resolution: resolution as i32,
+                range: scan_result,
+            },
+        );
         Ok(Some(RuntimeValue::from(ScannerSystem::to_user_heading(
             scan_result as f32,
         ))))
@@ -216,14 +351,36 @@ impl Runtime {
             return Ok(Some(RuntimeValue::from(0)));
         }
         let angle = ScannerSystem::to_real_heading(angle);
-        let mut launch_result = 0;
-        let mc = &self.game_state.motion_components.read()
-                  .unwrap()[&self.module_name];
+        // nothing to launch with, no need to wait on the game loop
+        if !self.snapshot().bot(&self.module_name).map_or(false, |b| b.can_fire) {
+            return Ok(Some(RuntimeValue::from(0)));
+        }
+
+        let reply = LaunchReply::default();
+        let queued = self.game_state.commands.push(
+            &self.module_name,
+            BotCommand::Cannon {
+                heading: angle,
+                range: range as u32,
+                reply: reply.clone(),
+            },
+        );
+        if !queued {
+            return Ok(Some(RuntimeValue::from(0)));
+        }
 
-        writelock(&self.game_state.projectile_components)
-            .entry(self.module_name.to_string())
-            .and_modify(|pc| launch_result =
-                        pc.launch(&mc.position, angle, range as u32));
+        // the launch is applied at the start of the next cycle, so wait for
+        // it to tell the bot whether it really fired
+        let launch_result = loop {
+            if let Some(launched) = *reply.lock().unwrap() {
+                break launched;
+            }
+            if self.game_state.clock.finished() {
+                break 0;
+            }
+            self.game_state.clock.wait_cycles(&self.module_name, 1);
+            self.check_stopped()?;
+        };
 
         Ok(Some(RuntimeValue::from(launch_result)))
     }
@@ -235,16 +392,15 @@ impl Runtime {
         let angle = ScannerSystem::to_real_heading(angle);
         let speed = speed.min(super::game::motion::MAX_ENGINE);
 
-        writelock(&self.game_state.motion_components)
-            .entry(self.module_name.to_string())
-            .and_modify(|mc| {
-                mc.origin = mc.position.clone();
-                mc.distance_along_heading = 0;
-                mc.heading = angle;
-                mc.desired_speed = speed;
-            });
+        let queued = self.game_state.commands.push(
+            &self.module_name,
+            BotCommand::Drive {
+                heading: angle,
+                speed,
+            },
+        );
 
-        Ok(Some(RuntimeValue::from(1_i32)))
+        Ok(Some(RuntimeValue::from(queued as i32)))
     }
 
     fn damage(&mut self) -> WasmRuntimeResult {
@@ -252,9 +408,8 @@ impl Runtime {
             return Ok(Some(RuntimeValue::from(100)));
         }
         Ok(
-            match readlock(&self.game_state.damage_components)
-                   .get(&self.module_name) {
-                Some(dc) => Some(RuntimeValue::from(dc.damage)),
+            match self.snapshot().bot(&self.module_name) {
+                Some(b) => Some(RuntimeValue::from(b.damage)),
                 None => None,
             },
         )
@@ -265,11 +420,10 @@ impl Runtime {
             return Ok(Some(RuntimeValue::from(-1)));
         }
         Ok(
-            match readlock(&self.game_state.motion_components)
-                   .get(&self.module_name) {
-                Some(mc) => {
+            match self.snapshot().bot(&self.module_name) {
+                Some(b) => {
                     let h = ScannerSystem::heading_to_target(
-                        &mc.position,
+                        &b.position,
                         &Point2::new(tx as f32, ty as f32),
                     );
                     Some(RuntimeValue::from(ScannerSystem::to_user_heading(h)))
@@ -284,9 +438,8 @@ impl Runtime {
             return Ok(Some(RuntimeValue::from(0)));
         }
         Ok(
-            match readlock(&self.game_state.motion_components)
-                   .get(&self.module_name) {
-                Some(mc) => Some(RuntimeValue::from(mc.speed)),
+            match self.snapshot().bot(&self.module_name) {
+                Some(b) => Some(RuntimeValue::from(b.speed)),
                 None => None,
             },
         )
@@ -294,9 +447,8 @@ impl Runtime {
 
     fn loc_x(&mut self) -> WasmRuntimeResult {
         Ok(
-            match readlock(&self.game_state.motion_components)
-                   .get(&self.module_name) {
-                Some(mc) => Some(RuntimeValue::from(mc.position.x as i32)),
+            match self.snapshot().bot(&self.module_name) {
+                Some(b) => Some(RuntimeValue::from(b.position.x as i32)),
                 None => None,
             },
         )
@@ -304,18 +456,164 @@ impl Runtime {
 
     fn loc_y(&mut self) -> WasmRuntimeResult {
         Ok(
-            match readlock(&self.game_state.motion_components)
-                   .get(&self.module_name) {
-                Some(mc) => Some(RuntimeValue::from(mc.position.y as i32)),
+            match self.snapshot().bot(&self.module_name) {
+                Some(b) => Some(RuntimeValue::from(b.position.y as i32)),
                 None => None,
             },
         )
     }
 
+    // Location of the bot's current objective, -1 when the game mode has none
+    fn objective_x(&mut self) -> WasmRuntimeResult {
+        let x = match self.snapshot().objectives.target_for(&self.module_name) {
+            Some(p) => p.x as i32,
+            None => -1,
+        };
+        Ok(Some(RuntimeValue::from(x)))
+    }
+
+    fn objective_y(&mut self) -> WasmRuntimeResult {
+        let y = match self.snapshot().objectives.target_for(&self.module_name) {
+            Some(p) => p.y as i32,
+            None => -1,
+        };
+        Ok(Some(RuntimeValue::from(y)))
+    }
+
+    fn objective_state(&mut self) -> WasmRuntimeResult {
+        Ok(Some(RuntimeValue::from(
+            self.snapshot().objectives.state_for(&self.module_name),
+        )))
+    }
+
+    fn score(&mut self) -> WasmRuntimeResult {
+        Ok(Some(RuntimeValue::from(
+            self.snapshot().objectives.score(&self.module_name) as i32,
+        )))
+    }
+
+    // Checks the sensor's cooldown against the latest snapshot and queues
+    // its use. Each sensor can be used at most once per snapshot.
+    fn use_sensor(&mut self, snapshot: &GameSnapshot, sensor: Sensor) -> bool {
+        let ready = match snapshot.bot(&self.module_name) {
+            Some(b) => b.scanner.ready(sensor, snapshot.cycle),
+            None => false,
+        };
+        if !ready || self.sensors_used.contains(&(sensor, snapshot.cycle)) {
+            return false;
+        }
+        if !self
+            .game_state
+            .commands
+            .push(&self.module_name, BotCommand::Sense { sensor })
+        {
+            return false;
+        }
+
+        self.sensors_used.retain(|(_, c)| *c == snapshot.cycle);
+        self.sensors_used.push((sensor, snapshot.cycle));
+        true
+    }
+
+    // Heading the last projectile hit came from, -1 if never hit or the
+    // sensor is cooling down
+    fn last_hit_heading(&mut self) -> WasmRuntimeResult {
+        if self.is_dead() {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+        let snapshot = self.snapshot();
+        if !self.use_sensor(&snapshot, Sensor::HitDirection) {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+        let heading = snapshot
+            .bot(&self.module_name)
+            .and_then(|b| b.scanner.last_hit_heading)
+            .map_or(-1, |h| ScannerSystem::to_user_heading(h as f32));
+        Ok(Some(RuntimeValue::from(heading)))
+    }
+
+    // Number of bots at close range, -1 if the sensor is cooling down
+    fn proximity(&mut self) -> WasmRuntimeResult {
+        if self.is_dead() {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+        let snapshot = self.snapshot();
+        if !self.use_sensor(&snapshot, Sensor::Proximity) {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+        Ok(Some(RuntimeValue::from(ScannerSystem::proximity(
+            &snapshot,
+            &self.module_name,
+        ))))
+    }
+
+    // Writes up to `max` (heading, range) pairs of i32s into the bot's memory
+    // at `ptr`. Returns the number of pairs written, -1 if the sensor is
+    // cooling down.
+    fn radar_sweep(&mut self, ptr: i32, max: i32) -> WasmRuntimeResult {
+        if self.is_dead() {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+        let snapshot = self.snapshot();
+        if !self.use_sensor(&snapshot, Sensor::RadarSweep) {
+            return Ok(Some(RuntimeValue::from(-1)));
+        }
+
+        let mut contacts = ScannerSystem::radar_sweep(&snapshot, &self.module_name);
+        contacts.truncate(max.max(0) as usize);
+
+        let bytes: Vec<u8> = contacts
+            .iter()
+            .flat_map(|&(h, r)| {
+                let mut pair = h.to_le_bytes().to_vec();
+                pair.extend_from_slice(&r.to_le_bytes());
+                pair
+            })
+            .collect();
+
+        match self.memory {
+            Some(ref m) => m.set(ptr as u32, &bytes).map_err(|e| {
+                Trap::from(Error {
+                    kind: Kind::InterpreterError(e),
+                })
+            })?,
+            None => {
+                return Err(Trap::from(Error {
+                    kind: Kind::ExportResolve("memory".to_string()),
+                }))
+            }
+        }
+        Ok(Some(RuntimeValue::from(contacts.len() as i32)))
+    }
+
+    // Blocks the bot until the game loop has advanced the given number of
+    // cycles. Returns the current cycle.
+    fn wait_cycles(&mut self, cycles: i32) -> WasmRuntimeResult {
+        let cycle = self
+            .game_state
+            .clock
+            .wait_cycles(&self.module_name, cycles.max(0) as u32);
+        Ok(Some(RuntimeValue::from(cycle as i32)))
+    }
+
+    // Blocks the bot until one of the events in the mask fires. Returns the
+    // events that fired, or 0 if the bot is dead or the game has ended.
+    fn wait_until_event(&mut self, mask: i32) -> WasmRuntimeResult {
+        if self.is_dead() {
+            // still yield a cycle so dead bots don't spin
+            self.game_state.clock.wait_cycles(&self.module_name, 1);
+            return Ok(Some(RuntimeValue::from(0)));
+        }
+        let fired = self
+            .game_state
+            .clock
+            .wait_for_event(&self.module_name, mask as u32);
+        Ok(Some(RuntimeValue::from(fired as i32)))
+    }
+
     fn rand(&mut self, limit: i32) -> WasmRuntimeResult {
         use rand::Rng;
-        let mut rng = rand::thread_rng();
-        let n: i32 = rng.gen_range(0, limit);
+        let n: i32 = self.rng.gen_range(0, limit);
 
         Ok(Some(RuntimeValue::from(n)))
     }
@@ -349,3 +647,5 @@ impl Runtime {
         ))))
     }
 }
+
+pub mod wasi;
@@ -0,0 +1,421 @@
+//! Stand-ins for the imports that WASI, AssemblyScript and wasm-bindgen
+//! toolchains add to a module, so bots built with them load unmodified.
+//! Nothing here touches the host: there are no files, arguments or
+//! environment, output goes to the game log and randomness and time come
+//! from the match.
+
+use super::*;
+use rand::Rng;
+
+pub const WASI_MODULE: &'static str = "wasi_snapshot_preview1";
+pub const WASI_UNSTABLE_MODULE: &'static str = "wasi_unstable";
+pub const WBINDGEN_MODULE: &'static str = "__wbindgen_placeholder__";
+pub const ENV_MODULE: &'static str = "env";
+
+// Exports run before `botinit`, or instead of it for command modules
+pub const WASI_INITIALIZE_NAME: &'static str = "_initialize";
+pub const WASI_START_NAME: &'static str = "_start";
+
+// Host function indexes continue from here so they never clash with the
+// bot API in the `env` namespace
+pub const WASI_BASE_INDEX: usize = 100;
+
+const FD_WRITE_NAME: &'static str = "fd_write";
+const FD_WRITE_INDEX: usize = WASI_BASE_INDEX;
+const FD_READ_NAME: &'static str = "fd_read";
+const FD_READ_INDEX: usize = WASI_BASE_INDEX + 1;
+const FD_CLOSE_NAME: &'static str = "fd_close";
+const FD_CLOSE_INDEX: usize = WASI_BASE_INDEX + 2;
+const FD_SEEK_NAME: &'static str = "fd_seek";
+const FD_SEEK_INDEX: usize = WASI_BASE_INDEX + 3;
+const FD_FDSTAT_GET_NAME: &'static str = "fd_fdstat_get";
+const FD_FDSTAT_GET_INDEX: usize = WASI_BASE_INDEX + 4;
+const FD_PRESTAT_GET_NAME: &'static str = "fd_prestat_get";
+const FD_PRESTAT_GET_INDEX: usize = WASI_BASE_INDEX + 5;
+const FD_PRESTAT_DIR_NAME_NAME: &'static str = "fd_prestat_dir_name";
+const FD_PRESTAT_DIR_NAME_INDEX: usize = WASI_BASE_INDEX + 6;
+const PROC_EXIT_NAME: &'static str = "proc_exit";
+const PROC_EXIT_INDEX: usize = WASI_BASE_INDEX + 7;
+const RANDOM_GET_NAME: &'static str = "random_get";
+const RANDOM_GET_INDEX: usize = WASI_BASE_INDEX + 8;
+const CLOCK_TIME_GET_NAME: &'static str = "clock_time_get";
+const CLOCK_TIME_GET_INDEX: usize = WASI_BASE_INDEX + 9;
+const ARGS_GET_NAME: &'static str = "args_get";
+const ARGS_GET_INDEX: usize = WASI_BASE_INDEX + 10;
+const ARGS_SIZES_GET_NAME: &'static str = "args_sizes_get";
+const ARGS_SIZES_GET_INDEX: usize = WASI_BASE_INDEX + 11;
+const ENVIRON_GET_NAME: &'static str = "environ_get";
+const ENVIRON_GET_INDEX: usize = WASI_BASE_INDEX + 12;
+const ENVIRON_SIZES_GET_NAME: &'static str = "environ_sizes_get";
+const ENVIRON_SIZES_GET_INDEX: usize = WASI_BASE_INDEX + 13;
+const SCHED_YIELD_NAME: &'static str = "sched_yield";
+const SCHED_YIELD_INDEX: usize = WASI_BASE_INDEX + 14;
+const ABORT_NAME: &'static str = "abort";
+const ABORT_INDEX: usize = WASI_BASE_INDEX + 15;
+const WBINDGEN_THROW_NAME: &'static str = "__wbindgen_throw";
+const WBINDGEN_THROW_INDEX: usize = WASI_BASE_INDEX + 16;
+
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_BADF: i32 = 8;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_SPIPE: i32 = 70;
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+
+// Each game cycle counts as one millisecond on the bot's clock
+const NANOS_PER_CYCLE: u64 = 1_000_000;
+// Longest single write kept in the game log
+const MAX_OUTPUT_BYTES: usize = 4096;
+// Most buffers a single `fd_write` can gather, as POSIX's IOV_MAX
+const MAX_IOVS: i32 = 1024;
+// Largest buffer a bot can fill with a single `random_get`
+const MAX_RANDOM_BYTES: u32 = 65536;
+
+/// Resolves compatibility imports for one namespace. In the `env`
+/// namespace the bot API takes precedence. A disabled resolver refuses the
+/// stubs, naming the option the bot needs.
+pub struct CompatImportResolver {
+    namespace: &'static str,
+    enabled: bool,
+}
+
+impl CompatImportResolver {
+    pub fn new(namespace: &'static str, enabled: bool) -> CompatImportResolver {
+        CompatImportResolver { namespace, enabled }
+    }
+}
+
+impl ModuleImportResolver for CompatImportResolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &Signature,
+    ) -> Result<FuncRef, InterpreterError> {
+        let func_ref = match self.namespace {
+            ENV_MODULE => gen_funcref(field_name),
+            _ => None,
+        };
+        let func_ref = match func_ref {
+            None if self.enabled => compat_funcref(self.namespace, field_name),
+            other => other,
+        };
+        func_ref.ok_or_else(|| unresolved(self.namespace, field_name))
+    }
+}
+
+fn compat_funcref(namespace: &str, field_name: &str) -> Option<FuncRef> {
+    match namespace {
+        ENV_MODULE => match field_name {
+            ABORT_NAME => Some(host(&[ValueType::I32; 4], None, ABORT_INDEX)),
+            _ => None,
+        },
+        WBINDGEN_MODULE => match field_name {
+            WBINDGEN_THROW_NAME => Some(host(&[ValueType::I32; 2], None, WBINDGEN_THROW_INDEX)),
+            _ => None,
+        },
+        _ => gen_wasi_funcref(field_name),
+    }
+}
+
+// The error for an import a bot can't be given. For one of the stubs, it
+// says that the bot needs the `wasi` option.
+pub fn unresolved(namespace: &str, field_name: &str) -> InterpreterError {
+    if compat_funcref(namespace, field_name).is_some() {
+        InterpreterError::Function(format!(
+            "{}::{} is only linked for bots started with the wasi option",
+            namespace, field_name
+        ))
+    } else {
+        InterpreterError::Function(format!("{}::{}", namespace, field_name))
+    }
+}
+
+fn host(params: &'static [ValueType], result: Option<ValueType>, index: usize) -> FuncRef {
+    FuncInstance::alloc_host(Signature::new(params, result), index)
+}
+
+fn gen_wasi_funcref(name: &str) -> Option<FuncRef> {
+    use wasmi::ValueType::{I32, I64};
+
+    match name {
+        FD_WRITE_NAME => Some(host(&[I32; 4], Some(I32), FD_WRITE_INDEX)),
+        FD_READ_NAME => Some(host(&[I32; 4], Some(I32), FD_READ_INDEX)),
+        FD_CLOSE_NAME => Some(host(&[I32], Some(I32), FD_CLOSE_INDEX)),
+        FD_SEEK_NAME => Some(host(&[I32, I64, I32, I32], Some(I32), FD_SEEK_INDEX)),
+        FD_FDSTAT_GET_NAME => Some(host(&[I32; 2], Some(I32), FD_FDSTAT_GET_INDEX)),
+        FD_PRESTAT_GET_NAME => Some(host(&[I32; 2], Some(I32), FD_PRESTAT_GET_INDEX)),
+        FD_PRESTAT_DIR_NAME_NAME => Some(host(&[I32; 3], Some(I32), FD_PRESTAT_DIR_NAME_INDEX)),
+        PROC_EXIT_NAME => Some(host(&[I32], None, PROC_EXIT_INDEX)),
+        RANDOM_GET_NAME => Some(host(&[I32; 2], Some(I32), RANDOM_GET_INDEX)),
+        CLOCK_TIME_GET_NAME => Some(host(&[I32, I64, I32], Some(I32), CLOCK_TIME_GET_INDEX)),
+        ARGS_GET_NAME => Some(host(&[I32; 2], Some(I32), ARGS_GET_INDEX)),
+        ARGS_SIZES_GET_NAME => Some(host(&[I32; 2], Some(I32), ARGS_SIZES_GET_INDEX)),
+        ENVIRON_GET_NAME => Some(host(&[I32; 2], Some(I32), ENVIRON_GET_INDEX)),
+        ENVIRON_SIZES_GET_NAME => Some(host(&[I32; 2], Some(I32), ENVIRON_SIZES_GET_INDEX)),
+        SCHED_YIELD_NAME => Some(host(&[], Some(I32), SCHED_YIELD_INDEX)),
+        _ => None,
+    }
+}
+
+fn errno(code: i32) -> WasmRuntimeResult {
+    Ok(Some(RuntimeValue::from(code)))
+}
+
+fn memory_trap(e: InterpreterError) -> Trap {
+    Trap::from(Error {
+        kind: Kind::InterpreterError(e),
+    })
+}
+
+impl Runtime {
+    pub(super) fn invoke_compat(&mut self, index: usize, args: RuntimeArgs) -> WasmRuntimeResult {
+        match index {
+            FD_WRITE_INDEX => self.fd_write(args.nth(0), args.nth(1), args.nth(2), args.nth(3)),
+            FD_READ_INDEX => self.fd_read(args.nth(0), args.nth(3)),
+            FD_CLOSE_INDEX => errno(ERRNO_BADF),
+            FD_SEEK_INDEX => self.fd_seek(args.nth(0)),
+            FD_FDSTAT_GET_INDEX => self.fd_fdstat_get(args.nth(0), args.nth(1)),
+            // no preopened directories, so the bot can't open files
+            FD_PRESTAT_GET_INDEX | FD_PRESTAT_DIR_NAME_INDEX => errno(ERRNO_BADF),
+            PROC_EXIT_INDEX => Err(Trap::from(Error {
+                kind: Kind::BotExited(self.module_name.to_string(), args.nth(0)),
+            })),
+            RANDOM_GET_INDEX => self.random_get(args.nth(0), args.nth(1)),
+            CLOCK_TIME_GET_INDEX => self.clock_time_get(args.nth(2)),
+            // no arguments or environment variables
+            ARGS_GET_INDEX | ENVIRON_GET_INDEX => errno(ERRNO_SUCCESS),
+            ARGS_SIZES_GET_INDEX | ENVIRON_SIZES_GET_INDEX => {
+                self.write_zero_sizes(args.nth(0), args.nth(1))
+            }
+            SCHED_YIELD_INDEX => {
+                self.game_state.clock.wait_cycles(&self.module_name, 1);
+                errno(ERRNO_SUCCESS)
+            }
+            ABORT_INDEX => self.abort(args.nth(0), args.nth(1), args.nth(2), args.nth(3)),
+            WBINDGEN_THROW_INDEX => self.wbindgen_throw(args.nth(0), args.nth(1)),
+            _ => Err(Trap::from(Error {
+                kind: Kind::MiscFailure("Invalid export index".to_string()),
+            })),
+        }
+    }
+
+    fn compat_memory(&self) -> Result<&MemoryRef, Trap> {
+        self.memory.as_ref().ok_or_else(|| {
+            Trap::from(Error {
+                kind: Kind::MiscFailure(format!(
+                    "bot {} does not export its memory",
+                    self.module_name
+                )),
+            })
+        })
+    }
+
+    // Writes to stdout and stderr end up in the game log, one event per write.
+    // Anything past MAX_OUTPUT_BYTES is reported as written but dropped.
+    fn fd_write(&mut self, fd: i32, iovs: i32, iovs_len: i32, nwritten: i32) -> WasmRuntimeResult {
+        if fd != 1 && fd != 2 {
+            return errno(ERRNO_BADF);
+        }
+        if iovs_len < 0 || iovs_len > MAX_IOVS {
+            return errno(ERRNO_INVAL);
+        }
+        let memory = self.compat_memory()?;
+
+        let mut written: u32 = 0;
+        let mut output = Vec::new();
+        for i in 0..iovs_len as u32 {
+            // each iovec is a (ptr, len) pair of u32s
+            let iov = match i.checked_mul(8).and_then(|o| (iovs as u32).checked_add(o)) {
+                Some(iov) => iov,
+                None => return errno(ERRNO_FAULT),
+            };
+            let (ptr, len) = match (
+                memory.get_value::<u32>(iov),
+                iov.checked_add(4).map(|at| memory.get_value::<u32>(at)),
+            ) {
+                (Ok(ptr), Some(Ok(len))) => (ptr, len),
+                _ => return errno(ERRNO_FAULT),
+            };
+            let keep = (len as usize).min(MAX_OUTPUT_BYTES - output.len());
+            match memory.get(ptr, keep) {
+                Ok(bytes) => output.extend(bytes),
+                Err(_) => return errno(ERRNO_FAULT),
+            }
+            written = match written.checked_add(len) {
+                Some(w) => w,
+                None => return errno(ERRNO_INVAL),
+            };
+        }
+        if memory.set_value(nwritten as u32, written).is_err() {
+            return errno(ERRNO_FAULT);
+        }
+
+        let text = String::from_utf8_lossy(&output)
+            .trim_end_matches('\n')
+            .to_string();
+        if !text.is_empty() {
+            self.game_state
+                .commands
+                .push(&self.module_name, BotCommand::Output { text });
+        }
+        errno(ERRNO_SUCCESS)
+    }
+
+    // stdin is always at end of file
+    fn fd_read(&mut self, fd: i32, nread: i32) -> WasmRuntimeResult {
+        if fd != 0 {
+            return errno(ERRNO_BADF);
+        }
+        self.compat_memory()?
+            .set_value(nread as u32, 0_u32)
+            .map_err(memory_trap)?;
+        errno(ERRNO_SUCCESS)
+    }
+
+    fn fd_seek(&mut self, fd: i32) -> WasmRuntimeResult {
+        if (0..=2).contains(&fd) {
+            errno(ERRNO_SPIPE)
+        } else {
+            errno(ERRNO_BADF)
+        }
+    }
+
+    fn fd_fdstat_get(&mut self, fd: i32, stat: i32) -> WasmRuntimeResult {
+        if !(0..=2).contains(&fd) {
+            return errno(ERRNO_BADF);
+        }
+        // fdstat is 24 bytes, led by the file type
+        let mut bytes = [0_u8; 24];
+        bytes[0] = FILETYPE_CHARACTER_DEVICE;
+        self.compat_memory()?
+            .set(stat as u32, &bytes)
+            .map_err(memory_trap)?;
+        errno(ERRNO_SUCCESS)
+    }
+
+    // Filled from the bot's match-seeded generator, so replays see the
+    // same bytes
+    fn random_get(&mut self, buf: i32, len: i32) -> WasmRuntimeResult {
+        if len < 0 || len as u32 > MAX_RANDOM_BYTES {
+            return errno(ERRNO_INVAL);
+        }
+        let mut bytes = vec![0_u8; len as usize];
+        self.rng.fill(&mut bytes[..]);
+        self.compat_memory()?
+            .set(buf as u32, &bytes)
+            .map_err(memory_trap)?;
+        errno(ERRNO_SUCCESS)
+    }
+
+    // Every clock reads game time, derived from the published cycle
+    fn clock_time_get(&mut self, time: i32) -> WasmRuntimeResult {
+        let now = (self.snapshot().cycle as u64 * NANOS_PER_CYCLE) as i64;
+        self.compat_memory()?
+            .set_value(time as u32, now)
+            .map_err(memory_trap)?;
+        errno(ERRNO_SUCCESS)
+    }
+
+    fn write_zero_sizes(&mut self, count: i32, buf_size: i32) -> WasmRuntimeResult {
+        let memory = self.compat_memory()?;
+        memory.set_value(count as u32, 0_u32).map_err(memory_trap)?;
+        memory
+            .set_value(buf_size as u32, 0_u32)
+            .map_err(memory_trap)?;
+        errno(ERRNO_SUCCESS)
+    }
+
+    // AssemblyScript's abort. Strings are UTF-16 with their byte length
+    // stored just before the pointer.
+    fn abort(&mut self, message: i32, file: i32, line: i32, column: i32) -> WasmRuntimeResult {
+        let message = self.read_utf16(message);
+        let file = self.read_utf16(file);
+        Err(Trap::from(Error {
+            kind: Kind::MiscFailure(format!(
+                "bot {} aborted: {} at {}:{}:{}",
+                self.module_name, message, file, line, column
+            )),
+        }))
+    }
+
+    fn wbindgen_throw(&mut self, ptr: i32, len: i32) -> WasmRuntimeResult {
+        let message = self
+            .compat_memory()?
+            .get(ptr as u32, (len.max(0) as usize).min(MAX_OUTPUT_BYTES))
+            .map(|b| String::from_utf8_lossy(&b).to_string())
+            .unwrap_or_default();
+        Err(Trap::from(Error {
+            kind: Kind::MiscFailure(format!("bot {} threw: {}", self.module_name, message)),
+        }))
+    }
+
+    fn read_utf16(&self, ptr: i32) -> String {
+        let memory = match self.memory {
+            Some(ref m) if ptr >= 4 => m,
+            _ => return String::new(),
+        };
+        let len = memory
+            .get_value::<u32>(ptr as u32 - 4)
+            .map(|l| (l as usize).min(MAX_OUTPUT_BYTES))
+            .unwrap_or(0);
+        let units: Vec<u16> = memory
+            .get(ptr as u32, len)
+            .unwrap_or_default()
+            .chunks(2)
+            .filter(|c| c.len() == 2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::GameState;
+    use wasmi::memory_units::Pages;
+    use wasmi::MemoryInstance;
+
+    fn runtime() -> Runtime {
+        let game_state = Arc::new(GameState::new());
+        let rng = game_state.bot_rng("bot");
+        Runtime {
+            game_state,
+            module_name: "bot".to_string(),
+            dead: false,
+            sensors_used: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            memory: Some(MemoryInstance::alloc(Pages(1), None).unwrap()),
+            rng,
+        }
+    }
+
+    #[test]
+    fn fd_write_faults_on_bad_iovecs() {
+        let mut rt = runtime();
+        let memory = rt.memory.clone().unwrap();
+        memory.set(64, b"hello\n").unwrap();
+        memory.set_value(0, 64_u32).unwrap();
+        memory.set_value(4, 6_u32).unwrap();
+
+        let code = |r: WasmRuntimeResult| match r {
+            Ok(Some(RuntimeValue::I32(code))) => code,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(ERRNO_SUCCESS, code(rt.fd_write(1, 0, 1, 16)));
+        assert_eq!(6_u32, memory.get_value(16).unwrap());
+
+        // iovecs running past the end of the address space or of memory
+        assert_eq!(ERRNO_FAULT, code(rt.fd_write(1, -4, 2, 16)));
+        assert_eq!(ERRNO_FAULT, code(rt.fd_write(1, 65532, 1, 16)));
+        assert_eq!(ERRNO_INVAL, code(rt.fd_write(1, 0, MAX_IOVS + 1, 16)));
+
+        let outputs = rt.game_state.commands.drain();
+        assert_eq!(1, outputs.len());
+        match outputs[0].1 {
+            BotCommand::Output { ref text } => assert_eq!("hello", text),
+            ref other => panic!("unexpected command {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use botengine::{BotLimits, GameMode};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const USAGE: &'static str = "usage: consolerunner [--match match.toml] \
+[--bot name=path.wasm ...] [--cycles n] [--seed n] [--rules rules.toml] \
+[--events out.ndjson] [--wasi] [--memory-pages n] [--call-depth n] \
+[--trusted-keys keys.txt]";
+
+const DEFAULT_CYCLES: u32 = 100_000;
+
+/// Everything needed to run a match. Can be loaded from a match file and
+/// then overridden from the command line.
+#[derive(Debug, Default, Deserialize)]
+pub struct MatchConfig {
+    #[serde(default)]
+    pub bot: Vec<BotEntry>,
+    pub cycles: Option<u32>,
+    pub seed: Option<u64>,
+    pub events: Option<String>,
+    pub rules: Option<Rules>,
+    // link WASI stubs for every bot
+    #[serde(default)]
+    pub wasi: bool,
+    // limits for bots that don't set their own
+    pub memory_pages: Option<u32>,
+    pub call_depth: Option<usize>,
+    // hex public keys, one per line; bots must be signed by one of them
+    pub trusted_keys: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BotEntry {
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub wasi: bool,
+    pub memory_pages: Option<u32>,
+    pub call_depth: Option<usize>,
+    // detached signature file
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Rules {
+    CycleLimit,
+    LastBotStanding,
+    CaptureTheFlag {
+        flag: [f32; 2],
+        captures_to_win: u32,
+    },
+    KingOfTheHill {
+        zone: [f32; 2],
+        radius: f32,
+        score_to_win: u32,
+    },
+}
+
+impl Rules {
+    pub fn game_mode(&self) -> GameMode {
+        match *self {
+            Rules::CycleLimit => GameMode::CycleLimit,
+            Rules::LastBotStanding => GameMode::LastBotStanding,
+            Rules::CaptureTheFlag {
+                flag,
+                captures_to_win,
+            } => GameMode::capture_the_flag(flag[0], flag[1], captures_to_win),
+            Rules::KingOfTheHill {
+                zone,
+                radius,
+                score_to_win,
+            } => GameMode::king_of_the_hill(zone[0], zone[1], radius, score_to_win),
+        }
+    }
+}
+
+impl MatchConfig {
+    // Builds a config from command line arguments (without the program
+    // name). A --match file is applied first, other flags override it.
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<MatchConfig, String> {
+        let args: Vec<String> = args.collect();
+        let mut config = match args.iter().position(|a| a == "--match") {
+            Some(i) => Self::load(value_of(&args, i)?)?,
+            None => MatchConfig::default(),
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--match" => {}
+                "--wasi" => {
+                    config.wasi = true;
+                    i += 1;
+                    continue;
+                }
+                "--bot" => config.bot.push(parse_bot(value_of(&args, i)?)?),
+                "--cycles" => config.cycles = Some(parse_number(&args, i)?),
+                "--seed" => config.seed = Some(parse_number(&args, i)?),
+                "--memory-pages" => config.memory_pages = Some(parse_number(&args, i)?),
+                "--call-depth" => config.call_depth = Some(parse_number(&args, i)?),
+                "--events" => config.events = Some(value_of(&args, i)?.to_string()),
+                "--trusted-keys" => config.trusted_keys = Some(value_of(&args, i)?.to_string()),
+                "--rules" => config.rules = Some(read_toml(value_of(&args, i)?)?),
+                other => return Err(format!("unknown argument {}\n{}", other, USAGE)),
+            }
+            i += 2;
+        }
+
+        // bots are players keyed by name, two under one name would share
+        // a single set of components
+        let mut names = HashSet::new();
+        if let Some(b) = config.bot.iter().find(|b| !names.insert(b.name.as_str())) {
+            return Err(format!("more than one bot is named {}", b.name));
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> Result<MatchConfig, String> {
+        read_toml(path)
+    }
+
+    // The bots to run, falling back to the bundled sample bots
+    pub fn bots(&self) -> Vec<BotEntry> {
+        if self.bot.is_empty() {
+            vec![
+                BotEntry {
+                    name: "bot-1".to_string(),
+                    path: "./bots/dumbotrs.wasm".to_string(),
+                    wasi: false,
+                    memory_pages: None,
+                    call_depth: None,
+                    signature: None,
+                },
+                BotEntry {
+                    name: "rook".to_string(),
+                    path: "./bots/rook.wasm".to_string(),
+                    wasi: false,
+                    memory_pages: None,
+                    call_depth: None,
+                    signature: None,
+                },
+                BotEntry {
+                    name: "rabbit".to_string(),
+                    path: "./bots/rabbit.wasm".to_string(),
+                    wasi: false,
+                    memory_pages: None,
+                    call_depth: None,
+                    signature: None,
+                },
+            ]
+        } else {
+            self.bot.clone()
+        }
+    }
+
+    pub fn limits_for(&self, bot: &BotEntry) -> BotLimits {
+        let defaults = BotLimits::default();
+        BotLimits {
+            memory_pages: bot
+                .memory_pages
+                .or(self.memory_pages)
+                .unwrap_or(defaults.memory_pages),
+            call_depth: bot
+                .call_depth
+                .or(self.call_depth)
+                .unwrap_or(defaults.call_depth),
+            ..defaults
+        }
+    }
+
+    pub fn cycles(&self) -> u32 {
+        self.cycles.unwrap_or(DEFAULT_CYCLES)
+    }
+
+    pub fn game_mode(&self) -> GameMode {
+        match self.rules {
+            Some(ref r) => r.game_mode(),
+            None => GameMode::CycleLimit,
+        }
+    }
+}
+
+fn value_of<'a>(args: &'a [String], i: usize) -> Result<&'a str, String> {
+    match args.get(i + 1) {
+        Some(v) => Ok(v),
+        None => Err(format!("{} needs a value\n{}", args[i], USAGE)),
+    }
+}
+
+fn parse_number<T: ::std::str::FromStr>(args: &[String], i: usize) -> Result<T, String> {
+    let v = value_of(args, i)?;
+    v.parse()
+        .map_err(|_| format!("{} expects a number, got {}", args[i], v))
+}
+
+fn parse_bot(spec: &str) -> Result<BotEntry, String> {
+    let mut parts = spec.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(path)) if !name.is_empty() && !path.is_empty() => Ok(BotEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+            wasi: false,
+            memory_pages: None,
+            call_depth: None,
+            signature: None,
+        }),
+        _ => Err(format!("--bot expects name=path.wasm, got {}", spec)),
+    }
+}
+
+fn read_toml<T>(path: &str) -> Result<T, String>
+where
+    T: for<'de> ::serde::Deserialize<'de>,
+{
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("unable to read {}: {}", path, e))?;
+    ::toml::from_str(&contents).map_err(|e| format!("invalid {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_command_line() {
+        let config =
+            MatchConfig::from_args(args("--bot a=a.wasm --cycles 500 --bot b=b.wasm --seed 7 --wasi --memory-pages 64").into_iter())
+                .unwrap();
+        assert_eq!(
+            vec!["a", "b"],
+            config.bots().iter().map(|b| b.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(500, config.cycles());
+        assert_eq!(Some(7), config.seed);
+        assert!(config.wasi);
+        assert_eq!(64, config.limits_for(&config.bots()[0]).memory_pages);
+        assert!(MatchConfig::from_args(args("--bot nopath").into_iter()).is_err());
+        assert!(MatchConfig::from_args(args("--cycles").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parses_match_file() {
+        let config: MatchConfig = ::toml::from_str(
+            r#"
+            cycles = 2000
+            [[bot]]
+            name = "rook"
+            path = "bots/rook.wasm"
+            wasi = true
+            call_depth = 500
+            [rules]
+            mode = "king_of_the_hill"
+            zone = [500.0, 500.0]
+            radius = 50.0
+            score_to_win = 300
+            "#,
+        )
+        .unwrap();
+        assert_eq!(2000, config.cycles());
+        assert_eq!(1, config.bots().len());
+        assert!(config.bots()[0].wasi);
+        assert_eq!(500, config.limits_for(&config.bots()[0]).call_depth);
+        assert_eq!(
+            Some(Rules::KingOfTheHill {
+                zone: [500.0, 500.0],
+                radius: 50.0,
+                score_to_win: 300,
+            }),
+            config.rules
+        );
+    }
+
+    #[test]
+    fn rejects_repeated_bot_names() {
+        assert!(MatchConfig::from_args(args("--bot a=x.wasm --bot a=y.wasm").into_iter()).is_err());
+
+        let path = std::env::temp_dir().join("consolerunner-repeated-names.toml");
+        std::fs::write(&path, "[[bot]]\nname = \"a\"\npath = \"x.wasm\"\n").unwrap();
+        let path = path.to_str().unwrap();
+        let from_file = format!("--match {} --bot a=y.wasm", path);
+        assert!(MatchConfig::from_args(args(&from_file).into_iter()).is_err());
+        let other_name = format!("--match {} --bot b=y.wasm", path);
+        assert!(MatchConfig::from_args(args(&other_name).into_iter()).is_ok());
+    }
+}
@@ -1,47 +1,161 @@
 extern crate botengine;
-use botengine::{Combatant, Gameloop};
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+use botengine::{BotOptions, Combatant, Gameloop};
+use config::{BotEntry, MatchConfig};
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread;
-use std::time;
 
-fn main() {    
-    let gs = Arc::new(botengine::GameState::new());
+mod config;
+
+fn main() {
+    let config = match MatchConfig::from_args(std::env::args().skip(1)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let gs = Arc::new(match config.seed {
+        Some(seed) => botengine::GameState::with_seed(seed),
+        None => botengine::GameState::new(),
+    });
+    println!("Match seed: {}", gs.seed);
 
-    let b1 = botengine::Combatant::buffer_from_file(
-        "./bots/dumbotrs.wasm");
-    let bot1 = b1.unwrap();
+    let bots = config.bots();
+    let buffers: Vec<_> = bots
+        .iter()
+        .map(|b| match Combatant::buffer_from_file(&b.path) {
+            Ok(buf) => buf,
+            Err(e) => {
+                eprintln!("unable to load bot {} from {}: {:?}", b.name, b.path, e);
+                process::exit(1);
+            }
+        })
+        .collect();
 
-    let b2 = botengine::Combatant::buffer_from_file(
-        "./bots/rook.wasm");
-    let bot2 = b2.unwrap();
+    let trusted = config.trusted_keys.as_ref().map(|keys| {
+        load_trusted_keys(keys).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    });
+    let options: Vec<_> = bots
+        .iter()
+        .map(|b| {
+            let options = BotOptions::new(b.wasi || config.wasi, config.limits_for(b));
+            match trusted {
+                Some(ref keys) => signed(options, keys, b).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }),
+                None => options,
+            }
+        })
+        .collect();
 
-    let rb = botengine::Combatant::buffer_from_file(
-        "./bots/rabbit.wasm");
-    let rabbit = rb.unwrap();
+    // events are written as one JSON object per line when a file is given
+    let mut events_out = match config.events {
+        Some(ref path) => match File::create(path) {
+            Ok(f) => Some(f),
+            Err(e) => {
+                eprintln!("unable to create {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
 
     let my_gs = gs.clone();
     let debug_gs = gs.clone();
 
     let (sender, receiver) = channel();
-    thread::spawn(move || loop {
-        match receiver.recv() {
-            Ok(ge) => println!("{:?}", ge),
-            Err(_) => {}
+    let logger = thread::spawn(move || {
+        for ge in receiver {
+            match events_out {
+                Some(ref mut f) => {
+                    let line = serde_json::to_string(&ge).unwrap();
+                    writeln!(f, "{}", line).unwrap();
+                }
+                None => println!("{:?}", ge),
+            }
         }
     });
 
-    let mut gl = Gameloop::new(my_gs, 100_000, 3, Some(sender));
+    let mut gl = Gameloop::builder(my_gs)
+        .max_cycles(config.cycles())
+        .num_combatants(bots.len())
+        .logger(Some(sender))
+        .mode(config.game_mode())
+        .with_default_systems()
+        .build();
 
-    let _handle = Combatant::start("bot-1", bot1, gs.clone());
-    let _handle2 = Combatant::start("rook", bot2, gs.clone());
-    let _handle3 = Combatant::start("rabbit", rabbit, gs.clone());
+    let _handles: Vec<_> = bots
+        .iter()
+        .zip(buffers.into_iter().zip(options))
+        .map(|(b, (buf, options))| Combatant::start_with_options(&b.name, buf, gs.clone(), options))
+        .collect();
     let game_result = gl.start();
 
-    thread::sleep(time::Duration::from_secs(1));
+    // dropping the loop releases its logger so the event stream can drain
+    drop(gl);
+    logger.join().unwrap();
 
     println!(
         "Game loop terminated: {:?}\nState: {:?}",
         game_result, debug_gs
     );
 }
+
+#[cfg(feature = "signed-bots")]
+type Trusted = Arc<botengine::TrustedKeys>;
+#[cfg(not(feature = "signed-bots"))]
+type Trusted = ();
+
+#[cfg(feature = "signed-bots")]
+fn load_trusted_keys(path: &str) -> Result<Trusted, String> {
+    botengine::TrustedKeys::from_file(path)
+        .map(Arc::new)
+        .map_err(|e| format!("unable to load trusted keys from {}: {}", path, e))
+}
+
+#[cfg(not(feature = "signed-bots"))]
+fn load_trusted_keys(_path: &str) -> Result<Trusted, String> {
+    Err("--trusted-keys needs consolerunner built with the signed-bots feature".to_string())
+}
+
+// Every bot needs a signature from one of the trusted keys, either in a
+// detached file (the bot's `signature`, or its path with `.sig` appended)
+// or embedded in the module. The engine checks it when loading the bot, and
+// a bot that fails the check sits the match out.
+#[cfg(feature = "signed-bots")]
+fn signed(options: BotOptions, trusted: &Trusted, bot: &BotEntry) -> Result<BotOptions, String> {
+    let sig_path = bot
+        .signature
+        .clone()
+        .unwrap_or_else(|| format!("{}.sig", bot.path));
+    let signature = match std::fs::read(&sig_path) {
+        Ok(sig) => Some(sig),
+        Err(_) if bot.signature.is_none() => None,
+        Err(e) => return Err(format!("unable to read {}: {}", sig_path, e)),
+    };
+    Ok(BotOptions {
+        trusted_keys: Some(trusted.clone()),
+        signature,
+        ..options
+    })
+}
+
+#[cfg(not(feature = "signed-bots"))]
+fn signed(options: BotOptions, _trusted: &Trusted, _bot: &BotEntry) -> Result<BotOptions, String> {
+    Ok(options)
+}
@@ -13,4 +13,13 @@ extern "C" {
     pub fn wtan(degree: i32) -> i32;
     pub fn watan(degree: i32) -> i32;
     pub fn plot_course(tx: i32, ty: i32) -> i32;
+    pub fn wait_cycles(cycles: i32) -> i32;
+    pub fn wait_until_event(mask: i32) -> i32;
+    pub fn objective_x() -> i32;
+    pub fn objective_y() -> i32;
+    pub fn objective_state() -> i32;
+    pub fn score() -> i32;
+    pub fn last_hit_heading() -> i32;
+    pub fn proximity() -> i32;
+    pub fn radar_sweep(ptr: i32, max: i32) -> i32;
 }
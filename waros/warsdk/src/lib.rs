@@ -53,6 +53,53 @@ pub fn plot_course(tx: i32, ty: i32) -> i32 {
     unsafe { ffi::plot_course(tx, ty) }
 }
 
+// Blocks until the game has advanced the given number of cycles
+pub fn wait_cycles(cycles: i32) -> i32 {
+    unsafe { ffi::wait_cycles(cycles) }
+}
+
+// Blocks until one of the EVENT_* flags in the mask fires, returning the
+// flags that fired (0 if the game is over)
+pub fn wait_until_event(mask: i32) -> i32 {
+    unsafe { ffi::wait_until_event(mask) }
+}
+
+// Location of the current objective (the flag, your home base while carrying
+// the flag, or the hill). -1 when the game mode has no objective.
+pub fn objective_x() -> i32 {
+    unsafe { ffi::objective_x() }
+}
+
+pub fn objective_y() -> i32 {
+    unsafe { ffi::objective_y() }
+}
+
+// One of the OBJECTIVE_* values
+pub fn objective_state() -> i32 {
+    unsafe { ffi::objective_state() }
+}
+
+pub fn score() -> i32 {
+    unsafe { ffi::score() }
+}
+
+// Heading the last projectile hit came from. -1 if never hit or the sensor
+// is cooling down.
+pub fn last_hit_heading() -> i32 {
+    unsafe { ffi::last_hit_heading() }
+}
+
+// Number of bots at close range, -1 if the sensor is cooling down
+pub fn proximity() -> i32 {
+    unsafe { ffi::proximity() }
+}
+
+// Fills `contacts` with (heading, range) pairs, nearest first. Returns the
+// number of pairs written, -1 if the sensor is cooling down.
+pub fn radar_sweep(contacts: &mut [i32]) -> i32 {
+    unsafe { ffi::radar_sweep(contacts.as_mut_ptr() as i32, (contacts.len() / 2) as i32) }
+}
+
 // Utility sample for moving to destination and stopping
 // Note - does NOT recover from collision en route
 pub fn go(target_x: i32, target_y: i32) {
@@ -64,11 +111,13 @@ pub fn go(target_x: i32, target_y: i32) {
           (target_y - loc_y()).abs() > 40 &&
            speed() > 0 {
         // wait till we get to the target
+        wait_cycles(1);
     }
 
     drive(course, 0); // turn off engine
     while speed() > 0 {
         // steady on until we stop
+        wait_cycles(1);
     }
 }
 
@@ -89,4 +138,16 @@ pub const BLAST_RADIUS: i32 = 40;
 
 pub const PROJECTILE_MAX_RANGE: u32 = 200;
 
+pub const EVENT_DAMAGED: i32 = 1;
+pub const EVENT_COLLISION: i32 = 2;
+pub const EVENT_RELOADED: i32 = 4;
+pub const EVENT_STOPPED: i32 = 8;
+
+pub const OBJECTIVE_NONE: i32 = 0;
+pub const OBJECTIVE_FREE: i32 = 1;
+pub const OBJECTIVE_HELD_BY_ME: i32 = 2;
+pub const OBJECTIVE_HELD_BY_OTHER: i32 = 3;
+
+pub const RADAR_MAX_CONTACTS: usize = 8;
+
 mod ffi;
@@ -9,11 +9,12 @@ use wasmi::{
 use super::imports::RuntimeModuleImportResolver;
 use super::runtime::Runtime;
 
-type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
-type Coordinate = (i32, i32);
+pub type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
+pub type Coordinate = (i32, i32);
 
-fn load_instance(import_resolver: &impl ModuleImportResolver,
-                 module_file: &str) -> Result<ModuleRef>  {
+pub fn load_instance(import_resolver: &impl ModuleImportResolver,
+                     import_module: &str,
+                     module_file: &str) -> Result<ModuleRef>  {
     let mut buffer = Vec::new();
     let mut f = File::open(module_file)?;
     f.read_to_end(&mut buffer)?;
@@ -23,7 +24,7 @@ fn load_instance(import_resolver: &impl ModuleImportResolver,
     let mut builder = ImportsBuilder::new();
 
     // assign the parent wrapper indicated in our wasm file (remember our import functions are under module "events)
-    builder.push_resolver("events", import_resolver);
+    builder.push_resolver(import_module, import_resolver);
 
     // return new wasm module instance with our own import resolver
     Ok(ModuleInstance::new(&module, &builder)
@@ -37,7 +38,7 @@ pub struct CheckersGame {
     module_instance: ModuleRef,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PieceColor {
     White,
     Black,
@@ -45,14 +46,20 @@ pub enum PieceColor {
 
 impl CheckersGame {
     pub fn new(module_file: &str) -> CheckersGame {
+        CheckersGame::with_runtime(module_file, Runtime::new())
+    }
+
+    // A game whose events are recorded rather than printed
+    pub fn quiet(module_file: &str) -> CheckersGame {
+        CheckersGame::with_runtime(module_file, Runtime::quiet())
+    }
+
+    fn with_runtime(module_file: &str, runtime: Runtime) -> CheckersGame {
         // create our own runtime module resolver
         let resolver = RuntimeModuleImportResolver::new();
 
         // create instance of wasm module
-        let instance = load_instance(&resolver, module_file).unwrap();
-
-        // create instance of our runtime
-        let runtime = Runtime::new();
+        let instance = load_instance(&resolver, "events", module_file).unwrap();
 
         // return checkers game with wasm modile instance and our runtime
         CheckersGame {
@@ -106,6 +113,36 @@ impl CheckersGame {
         }
     }
 
+    pub fn get_piece(&mut self, at: &Coordinate) -> Result<i32> {
+        let res = self.module_instance.invoke_export(
+            "getPiece",
+            &[RuntimeValue::from(at.0), RuntimeValue::from(at.1)],
+            &mut self.runtime,
+        )?;
+        match res {
+            Some(RuntimeValue::I32(v)) => Ok(v),
+            _ => Err(From::from("Bad invocation")),
+        }
+    }
+
+    // Writes a piece straight into the module's memory. The module has no
+    // export for this, so it's how a position gets set up.
+    pub fn set_piece(&mut self, at: &Coordinate, piece: i32) -> Result<()> {
+        match self.module_instance.export_by_name("memory") {
+            Some(ExternVal::Memory(mr)) => {
+                let offset = calc_offset(at.0 as usize, at.1 as usize);
+                mr.set(offset, &(piece as u32).to_le_bytes())?;
+                Ok(())
+            }
+            _ => Err(From::from("no memory export")),
+        }
+    }
+
+    // Squares pieces were crowned on since the last call
+    pub fn take_crowned(&mut self) -> Vec<Coordinate> {
+        self.runtime.take_crowned()
+    }
+
     pub fn get_board_contents(&mut self) -> Result<String> {
         let export = self.module_instance.export_by_name("memory");
         let header = r#"
@@ -0,0 +1,296 @@
+use std::fmt;
+
+use super::checkersgame::{CheckersGame, Coordinate, PieceColor, Result};
+use super::rustgame::RustCheckersGame;
+
+// Plays the same moves against the WAT checkers module and rustycheckers and
+// reports wherever they disagree: whether a move is legal, the board after
+// it, whose turn it is, or which pieces were crowned.
+//
+// The WAT module sets up a much smaller starting position, so every game
+// first copies rustycheckers' start into the WAT module's memory. Coordinates
+// are rustycheckers', with black at the high rows moving first.
+
+type Step = (Coordinate, Coordinate);
+
+// Longest random game before we give up on it ending
+const MAX_PLIES: usize = 150;
+
+// Hand-picked sequences, run before the random games
+const SCRIPTS: &[(&str, &[Step])] = &[
+    (
+        "opening steps",
+        &[((0, 5), (1, 4)), ((1, 2), (0, 3)), ((2, 5), (3, 4)), ((3, 2), (2, 3))],
+    ),
+    (
+        "forced capture",
+        &[((2, 5), (3, 4)), ((5, 2), (4, 3)), ((3, 4), (5, 2))],
+    ),
+    (
+        "illegal moves",
+        &[
+            // out of turn
+            ((1, 2), (0, 3)),
+            // sideways
+            ((0, 5), (0, 4)),
+            // onto a friendly piece
+            ((0, 5), (1, 6)),
+            // a jump over nothing
+            ((0, 5), (2, 3)),
+            ((0, 5), (1, 4)),
+        ],
+    ),
+];
+
+/// Where the two engines first disagreed in a game
+#[derive(Debug, PartialEq)]
+pub enum Divergence {
+    Legality { wat: bool, rust: i32 },
+    Board { at: Coordinate, wat: i32, rust: i32 },
+    TurnOwner { wat: PieceColor, rust: PieceColor },
+    Crowning { wat: Vec<Coordinate>, rust: Vec<Coordinate> },
+    Trap { module: &'static str, error: String },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::Legality { wat, rust } => write!(
+                f,
+                "the WAT module {} the move, rustycheckers returned {}",
+                if *wat { "accepted" } else { "rejected" },
+                rust
+            ),
+            Divergence::Board { at, wat, rust } => write!(
+                f,
+                "({},{}) holds {} in the WAT module and {} in rustycheckers",
+                at.0, at.1, wat, rust
+            ),
+            Divergence::TurnOwner { wat, rust } => write!(
+                f,
+                "it is {:?}'s turn in the WAT module and {:?}'s in rustycheckers",
+                wat, rust
+            ),
+            Divergence::Crowning { wat, rust } => write!(
+                f,
+                "the WAT module crowned on {:?}, rustycheckers on {:?}",
+                wat, rust
+            ),
+            Divergence::Trap { module, error } => write!(f, "{} trapped: {}", module, error),
+        }
+    }
+}
+
+// xorshift64*, so runs can be replayed from their seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// The same game loaded into both modules
+struct Pair {
+    wat: CheckersGame,
+    rust: RustCheckersGame,
+}
+
+impl Pair {
+    // Starts a game in each module and copies rustycheckers' position into
+    // the WAT one. Returns the squares the two started out differing on.
+    fn start(wat_file: &str, rust_file: &str) -> Result<(Pair, Vec<Coordinate>)> {
+        let mut wat = CheckersGame::quiet(wat_file);
+        wat.init()?;
+        let mut rust = RustCheckersGame::new(rust_file)?;
+
+        let mut differing = Vec::new();
+        for at in squares() {
+            let piece = rust.get_piece(&at)?;
+            if wat.get_piece(&at)? != piece {
+                differing.push(at);
+                wat.set_piece(&at, piece)?;
+            }
+        }
+        Ok((Pair { wat, rust }, differing))
+    }
+
+    // Plays `step` in both modules, returning where they part ways if they do
+    fn play(&mut self, step: &Step) -> Result<Option<Divergence>> {
+        let (from, to) = step;
+        let wat = match self.wat.move_piece(from, to) {
+            Ok(accepted) => accepted,
+            Err(e) => return Ok(Some(trap("the WAT module", e))),
+        };
+        let rust = match self.rust.move_piece(from, to) {
+            Ok(result) => result,
+            Err(e) => return Ok(Some(trap("rustycheckers", e))),
+        };
+        if wat != (rust != 0) {
+            return Ok(Some(Divergence::Legality { wat, rust }));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Option<Divergence>> {
+        let (wat, rust) = (self.wat.take_crowned(), self.rust.take_crowned());
+        if wat != rust {
+            return Ok(Some(Divergence::Crowning { wat, rust }));
+        }
+        let (wat, rust) = (self.wat.get_turn_owner()?, self.rust.get_turn_owner()?);
+        if wat != rust {
+            return Ok(Some(Divergence::TurnOwner { wat, rust }));
+        }
+        for at in squares() {
+            let (wat, rust) = (self.wat.get_piece(&at)?, self.rust.get_piece(&at)?);
+            if wat != rust {
+                return Ok(Some(Divergence::Board { at, wat, rust }));
+            }
+        }
+        Ok(None)
+    }
+
+    // A move to try: usually one rustycheckers allows, otherwise a nearby
+    // square that may or may not be legal. Targets stay on the board, since
+    // the WAT module reads memory out of bounds, and traps, for squares off it.
+    fn pick(&mut self, rng: &mut Rng) -> Result<Option<Step>> {
+        let legal = self.rust.legal_moves()?;
+        if legal.is_empty() {
+            return Ok(None);
+        }
+        let (from, to) = legal[rng.below(legal.len())];
+        if rng.below(4) != 0 {
+            return Ok(Some((from, to)));
+        }
+        let on_board = |v: i32| (0..8).contains(&v);
+        let target = loop {
+            let (dx, dy) = (rng.below(5) as i32 - 2, rng.below(5) as i32 - 2);
+            let target = (from.0 + dx, from.1 + dy);
+            if (dx, dy) != (0, 0) && on_board(target.0) && on_board(target.1) {
+                break target;
+            }
+        };
+        Ok(Some((from, target)))
+    }
+}
+
+fn squares() -> impl Iterator<Item = Coordinate> {
+    (0..8).flat_map(|y| (0..8).map(move |x| (x, y)))
+}
+
+fn trap(module: &'static str, error: Box<dyn std::error::Error>) -> Divergence {
+    Divergence::Trap {
+        module,
+        error: error.to_string(),
+    }
+}
+
+fn report(game: &str, ply: usize, step: &Step, divergence: &Divergence) {
+    let ((fx, fy), (tx, ty)) = step;
+    println!(
+        "{}, ply {} ({},{})->({},{}): {}",
+        game, ply, fx, fy, tx, ty, divergence
+    );
+}
+
+// Plays a script from the start, returning the ply the modules first
+// disagreed on and how
+fn play_script(
+    wat_file: &str,
+    rust_file: &str,
+    script: &[Step],
+) -> Result<Option<(usize, Divergence)>> {
+    let (mut pair, _) = Pair::start(wat_file, rust_file)?;
+    for (ply, step) in script.iter().enumerate() {
+        if let Some(divergence) = pair.play(step)? {
+            return Ok(Some((ply, divergence)));
+        }
+    }
+    Ok(None)
+}
+
+/// Runs the scripted games and then `games` random ones from `seed`.
+/// Returns how many games diverged.
+pub fn run(wat_file: &str, rust_file: &str, games: usize, seed: u64) -> Result<usize> {
+    let mut diverged = 0;
+
+    let (_, differing) = Pair::start(wat_file, rust_file)?;
+    if !differing.is_empty() {
+        println!(
+            "start positions differ on {} squares; the WAT module is set up from rustycheckers' start",
+            differing.len()
+        );
+    }
+    for (name, script) in SCRIPTS {
+        if let Some((ply, divergence)) = play_script(wat_file, rust_file, script)? {
+            report(&format!("script \"{}\"", name), ply, &script[ply], &divergence);
+            diverged += 1;
+        }
+    }
+
+    let mut rng = Rng::new(seed);
+    for game in 0..games {
+        let (mut pair, _) = Pair::start(wat_file, rust_file)?;
+        for ply in 0..MAX_PLIES {
+            let step = match pair.pick(&mut rng)? {
+                Some(step) => step,
+                None => break,
+            };
+            if let Some(divergence) = pair.play(&step)? {
+                report(&format!("random game {}", game), ply, &step, &divergence);
+                diverged += 1;
+                break;
+            }
+        }
+    }
+
+    println!(
+        "{} of {} games diverged (seed {})",
+        diverged,
+        SCRIPTS.len() + games,
+        seed
+    );
+    Ok(diverged)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Both need rustycheckers built for wasm32-unknown-unknown, which
+    // `make conform` does before running them
+    const WAT_FILE: &str = "./checkers.wat.wasm";
+
+    fn script(name: &str) -> &'static [Step] {
+        SCRIPTS.iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    #[test]
+    #[ignore]
+    fn opening_steps_agree() {
+        let result = play_script(WAT_FILE, crate::RUSTYCHECKERS_WASM, script("opening steps"));
+        assert_eq!(None, result.unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn wat_module_leaves_captured_pieces() {
+        let result = play_script(WAT_FILE, crate::RUSTYCHECKERS_WASM, script("forced capture"));
+        // the jump is legal in both, but only rustycheckers takes the piece
+        match result.unwrap() {
+            Some((2, Divergence::Board { at: (4, 3), wat, rust: 0 })) => assert_ne!(0, wat),
+            other => panic!("expected the jumped piece to differ, got {:?}", other),
+        }
+    }
+}
@@ -5,6 +5,9 @@ use wasmi::{
 
 pub const PIECEMOVED_INDEX: usize = 0;
 pub const PIECECROWNED_INDEX: usize = 1;
+pub const ENV_PIECEMOVED_INDEX: usize = 2;
+pub const ENV_PIECECROWNED_INDEX: usize = 3;
+pub const ENV_GAMEOVER_INDEX: usize = 4;
 
 pub struct RuntimeModuleImportResolver;
 
@@ -18,7 +21,7 @@ impl RuntimeModuleImportResolver {
 // for function execution.
 // It will match the function signature to a specified index
 // which our runtime will use to invoke the correct function.
-impl<'a> ModuleImportResolver for RuntimeModuleImportResolver {
+impl ModuleImportResolver for RuntimeModuleImportResolver {
     fn resolve_func(
         &self,
         field_name: &str,
@@ -51,3 +54,35 @@ impl<'a> ModuleImportResolver for RuntimeModuleImportResolver {
         Ok(func_ref)
     }
 }
+
+// rustycheckers imports its notifications from "env", each taking the
+// game handle as its first argument.
+pub struct EnvModuleImportResolver;
+
+impl EnvModuleImportResolver {
+    pub fn new() -> EnvModuleImportResolver {
+        EnvModuleImportResolver {}
+    }
+}
+
+impl ModuleImportResolver for EnvModuleImportResolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &Signature,
+    ) -> Result<FuncRef, InterpreterError> {
+        let (params, index) = match field_name {
+            "notify_piecemoved" => (5, ENV_PIECEMOVED_INDEX),
+            "notify_piececrowned" => (3, ENV_PIECECROWNED_INDEX),
+            "notify_gameover" => (2, ENV_GAMEOVER_INDEX),
+            _ => {
+                return Err(InterpreterError::Function(format!(
+                    "env module doesn't export function with name {}",
+                    field_name
+                )))
+            }
+        };
+        let params = vec![ValueType::I32; params];
+        Ok(FuncInstance::alloc_host(Signature::new(params, None), index))
+    }
+}
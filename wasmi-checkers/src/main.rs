@@ -1,13 +1,24 @@
 extern crate wasmi;
 
 mod checkersgame;
+mod conformance;
 mod imports;
 mod runtime;
+mod rustgame;
 
 use checkersgame::CheckersGame;
+use std::env;
 use std::error::Error;
 
+const RUSTYCHECKERS_WASM: &str =
+    "../rustycheckers/target/wasm32-unknown-unknown/release/rustycheckers.wasm";
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("conform") {
+        return conform(&args[2..]);
+    }
+
     let mut game = CheckersGame::new("./checkers.wat.wasm");
     game.init()?;
 
@@ -36,3 +47,23 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+// conform [wat module] [rustycheckers module] [random games] [seed]
+fn conform(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let wat_file = args.first().map_or("./checkers.wat.wasm", String::as_str);
+    let rust_file = args.get(1).map_or(RUSTYCHECKERS_WASM, String::as_str);
+    let games = match args.get(2) {
+        Some(games) => games.parse()?,
+        None => 100,
+    };
+    let seed = match args.get(3) {
+        Some(seed) => seed.parse()?,
+        None => 1,
+    };
+
+    let diverged = conformance::run(wat_file, rust_file, games, seed)?;
+    if diverged > 0 {
+        return Err(From::from(format!("{} games diverged", diverged)));
+    }
+    Ok(())
+}
@@ -1,14 +1,37 @@
-use super::imports::{PIECECROWNED_INDEX, PIECEMOVED_INDEX};
+use super::imports::{
+    ENV_GAMEOVER_INDEX, ENV_PIECECROWNED_INDEX, ENV_PIECEMOVED_INDEX, PIECECROWNED_INDEX,
+    PIECEMOVED_INDEX,
+};
 
 use wasmi::{Externals, RuntimeArgs, RuntimeValue, Trap};
 
 // We will implement a runtime that handle the execution of imported
 // functions in the wasm module.
-pub struct Runtime {} // (1)
+pub struct Runtime { // (1)
+    // print every event as it arrives
+    verbose: bool,
+    // squares a piece was crowned on since the last take_crowned
+    crowned: Vec<(i32, i32)>,
+}
 
 impl Runtime {
     pub fn new() -> Runtime {
-        Runtime {}
+        Runtime {
+            verbose: true,
+            crowned: Vec::new(),
+        }
+    }
+
+    // A runtime that only records events, for the conformance harness
+    pub fn quiet() -> Runtime {
+        Runtime {
+            verbose: false,
+            crowned: Vec::new(),
+        }
+    }
+
+    pub fn take_crowned(&mut self) -> Vec<(i32, i32)> {
+        std::mem::take(&mut self.crowned)
     }
 
     fn handle_piece_moved( // (2)
@@ -16,17 +39,29 @@ impl Runtime {
         from: (i32, i32),
         to: (i32, i32),
     ) -> Result<Option<RuntimeValue>, Trap> {
-        println!(
-            "A piece was moved from ({},{}) to ({},{})",
-            from.0, from.1, to.0, to.1
-        );
+        if self.verbose {
+            println!(
+                "A piece was moved from ({},{}) to ({},{})",
+                from.0, from.1, to.0, to.1
+            );
+        }
         Ok(None)
     }
 
     fn handle_piece_crowned( // (3)
-        &self,
+        &mut self,
         loc: (i32, i32)) -> Result<Option<RuntimeValue>, Trap> {
-        println!("A piece was crowned at ({},{})", loc.0, loc.1);
+        if self.verbose {
+            println!("A piece was crowned at ({},{})", loc.0, loc.1);
+        }
+        self.crowned.push(loc);
+        Ok(None)
+    }
+
+    fn handle_game_over(&self, status: i32) -> Result<Option<RuntimeValue>, Trap> {
+        if self.verbose {
+            println!("The game ended with status {}", status);
+        }
         Ok(None)
     }
 }
@@ -52,6 +87,23 @@ impl Externals for Runtime {
                 let to_y: i32 = args.nth(3);
                 self.handle_piece_moved((from_x, from_y), (to_x, to_y))
             }
+            // rustycheckers passes the game handle first, which we skip
+            ENV_PIECEMOVED_INDEX => {
+                let from_x: i32 = args.nth(1);
+                let from_y: i32 = args.nth(2);
+                let to_x: i32 = args.nth(3);
+                let to_y: i32 = args.nth(4);
+                self.handle_piece_moved((from_x, from_y), (to_x, to_y))
+            }
+            ENV_PIECECROWNED_INDEX => {
+                let piece_x: i32 = args.nth(1);
+                let piece_y: i32 = args.nth(2);
+                self.handle_piece_crowned((piece_x, piece_y))
+            }
+            ENV_GAMEOVER_INDEX => {
+                let status: i32 = args.nth(1);
+                self.handle_game_over(status)
+            }
             _ => panic!("unknown function index"),
         }
     }
@@ -0,0 +1,93 @@
+use wasmi::{ModuleRef, RuntimeValue};
+
+use super::checkersgame::{load_instance, Coordinate, PieceColor, Result};
+use super::imports::EnvModuleImportResolver;
+use super::runtime::Runtime;
+
+/// One game in the rustycheckers wasm module, driven through its
+/// handle-based exports
+pub struct RustCheckersGame {
+    runtime: Runtime,
+    module_instance: ModuleRef,
+    id: i32,
+}
+
+impl RustCheckersGame {
+    // Loads the module and starts an American game in it
+    pub fn new(module_file: &str) -> Result<RustCheckersGame> {
+        let resolver = EnvModuleImportResolver::new();
+        let module_instance = load_instance(&resolver, "env", module_file)?;
+        let mut game = RustCheckersGame {
+            runtime: Runtime::quiet(),
+            module_instance,
+            id: 0,
+        };
+        game.id = game.call("new_game", &[])?;
+        Ok(game)
+    }
+
+    fn call(&mut self, name: &str, args: &[RuntimeValue]) -> Result<i32> {
+        match self
+            .module_instance
+            .invoke_export(name, args, &mut self.runtime)?
+        {
+            Some(RuntimeValue::I32(v)) => Ok(v),
+            _ => Err(From::from(format!("Bad invocation of {}", name))),
+        }
+    }
+
+    // 0 for an illegal move, 1 when the turn passes and 2 when the same
+    // piece has to keep jumping
+    pub fn move_piece(&mut self, from: &Coordinate, to: &Coordinate) -> Result<i32> {
+        let args = [
+            RuntimeValue::from(self.id),
+            RuntimeValue::from(from.0),
+            RuntimeValue::from(from.1),
+            RuntimeValue::from(to.0),
+            RuntimeValue::from(to.1),
+        ];
+        self.call("move_piece", &args)
+    }
+
+    // The piece at `at` in the WAT module's encoding, 0 for an empty square
+    pub fn get_piece(&mut self, at: &Coordinate) -> Result<i32> {
+        let args = [
+            RuntimeValue::from(self.id),
+            RuntimeValue::from(at.0),
+            RuntimeValue::from(at.1),
+        ];
+        self.call("get_piece", &args).map(|piece| piece.max(0))
+    }
+
+    pub fn get_turn_owner(&mut self) -> Result<PieceColor> {
+        let id = RuntimeValue::from(self.id);
+        match self.call("get_current_turn", &[id])? {
+            1 => Ok(PieceColor::Black),
+            2 => Ok(PieceColor::White),
+            _ => Err(From::from("Bad invocation")),
+        }
+    }
+
+    pub fn legal_moves(&mut self) -> Result<Vec<(Coordinate, Coordinate)>> {
+        let id = RuntimeValue::from(self.id);
+        let count = self.call("get_legal_move_count", &[id])?;
+        let mut moves = Vec::new();
+        for index in 0..count {
+            let packed = self.call("get_legal_move", &[id, RuntimeValue::from(index)])?;
+            moves.push(unpack_move(packed));
+        }
+        Ok(moves)
+    }
+
+    // Squares pieces were crowned on since the last call
+    pub fn take_crowned(&mut self) -> Vec<Coordinate> {
+        self.runtime.take_crowned()
+    }
+}
+
+// Moves come packed four bits per coordinate:
+// fromX << 12 | fromY << 8 | toX << 4 | toY
+fn unpack_move(packed: i32) -> (Coordinate, Coordinate) {
+    let nibble = |shift: i32| (packed >> shift) & 0xf;
+    ((nibble(12), nibble(8)), (nibble(4), nibble(0)))
+}